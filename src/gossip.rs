@@ -1,8 +1,9 @@
 use crate::{
-    Subscription, SubscriptionError, SubscriptionSlice, Subscriptions, SubscriptionsSlice,
+    Subscription, SubscriptionError, SubscriptionSlice, Subscriptions, SubscriptionsSlice, Topic,
 };
 use keynesis::{key::ed25519, passport::block::Time};
 use std::{
+    collections::HashSet,
     convert::TryInto as _,
     fmt::{self, Formatter},
     net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
@@ -31,6 +32,20 @@ pub struct Gossip(Vec<u8>);
 #[derive(Copy, Clone, Eq, PartialEq, Hash)]
 pub struct GossipSlice<'a>(&'a [u8]);
 
+/// collect the gossips to send to a peer, see
+/// [`Topology::gossips_for_with`](crate::Topology::gossips_for_with)
+#[derive(Debug, Clone, Default)]
+pub struct GossipsBuilder {
+    gossips: Vec<Gossip>,
+    last: Option<Gossip>,
+    ids: HashSet<ed25519::PublicKey>,
+    size: usize,
+
+    max_bytes: Option<usize>,
+    max: Option<usize>,
+    topic: Option<Topic>,
+}
+
 #[derive(Debug, Error)]
 pub enum GossipError {
     #[error("Invalid gossip size, expected at least {min}")]
@@ -149,6 +164,16 @@ impl Gossip {
         address: SocketAddr,
         id: &ed25519::SecretKey,
         subscriptions: SubscriptionsSlice<'_>,
+    ) -> Self {
+        Self::new_at(address, id, subscriptions, Time::now())
+    }
+
+    /// prepare a gossip as if it was created at the given time
+    pub(crate) fn new_at(
+        address: SocketAddr,
+        id: &ed25519::SecretKey,
+        subscriptions: SubscriptionsSlice<'_>,
+        time: Time,
     ) -> Self {
         let mut info = GossipInfo(0);
         info.set_num_subscriptions(subscriptions.number_subscriptions());
@@ -165,7 +190,7 @@ impl Gossip {
 
         bytes[INFO_INDEX..INFO_END].copy_from_slice(&info.0.to_be_bytes());
         bytes[ID_INDEX..ID_END].copy_from_slice(id.public_key().as_ref());
-        bytes[TIME_INDEX..TIME_END].copy_from_slice(&Time::now().to_be_bytes());
+        bytes[TIME_INDEX..TIME_END].copy_from_slice(&time.to_be_bytes());
 
         match address.ip() {
             IpAddr::V4(v4) => {
@@ -191,6 +216,12 @@ impl Gossip {
         GossipSlice(&self.0)
     }
 
+    /// the number of bytes of the encoded gossip
+    #[inline]
+    pub fn wire_size(&self) -> usize {
+        self.0.len()
+    }
+
     pub fn id(&self) -> ed25519::PublicKey {
         self.as_slice().id()
     }
@@ -212,6 +243,136 @@ impl Gossip {
     }
 }
 
+impl GossipsBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// collect only the gossips of the nodes subscribed to the given topic
+    ///
+    /// The layers are then populated for this topic only (instead of all
+    /// the topics the recipient is subscribed to) so the gossips are
+    /// relevant to maintain the recipient's links for that topic.
+    pub fn for_topic(topic: Topic) -> Self {
+        Self {
+            topic: Some(topic),
+            ..Self::default()
+        }
+    }
+
+    /// the topic the gossips are scoped to, see [`GossipsBuilder::for_topic`]
+    pub fn topic(&self) -> Option<Topic> {
+        self.topic
+    }
+
+    /// limit the total number of bytes of the collected gossips (see
+    /// [`Gossip::wire_size`]), the gossips that would exceed this limit
+    /// are not added. The limit covers all the built gossips, including
+    /// our own gossip added by the [`Topology`](crate::Topology).
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// limit the number of collected gossips, the gossips added once the
+    /// limit is reached are not added. Like [`GossipsBuilder::with_max_bytes`]
+    /// the limit covers all the built gossips, our own gossip included.
+    ///
+    /// The gossips are kept on a first come, first served basis: see
+    /// [`Topology::gossips_for_with`](crate::Topology::gossips_for_with)
+    /// for the order in which they are added.
+    pub fn with_max(mut self, max: usize) -> Self {
+        self.max = Some(max);
+        self
+    }
+
+    /// add the gossip, returns `false` if the gossip was not added
+    /// because it exceeds the limits of the builder, because it is
+    /// not subscribed to the builder's topic or because a gossip of the
+    /// same node was already added
+    pub fn add(&mut self, gossip: Gossip) -> bool {
+        if self.contains(&gossip.id()) {
+            return false;
+        }
+
+        if matches!(self.max, Some(max) if self.len() >= max) {
+            return false;
+        }
+
+        if let Some(topic) = self.topic {
+            if !gossip
+                .subscriptions()
+                .iter()
+                .any(|subscription| subscription.topic() == topic)
+            {
+                return false;
+            }
+        }
+
+        let size = self.size + gossip.wire_size();
+        if let Some(max_bytes) = self.max_bytes {
+            if size > max_bytes {
+                return false;
+            }
+        }
+
+        self.size = size;
+        self.ids.insert(gossip.id());
+        self.gossips.push(gossip);
+        true
+    }
+
+    /// add the gossip at the end of the built gossips, whatever the topic
+    /// of the builder. Its size is reserved right away so the gossips
+    /// added afterwards cannot push it over the limits. Returns `false`
+    /// if the gossip does not fit or if a gossip of the same node was
+    /// already added.
+    pub(crate) fn add_last(&mut self, gossip: Gossip) -> bool {
+        if self.last.is_some() || self.contains(&gossip.id()) {
+            return false;
+        }
+
+        if matches!(self.max, Some(max) if self.len() >= max) {
+            return false;
+        }
+
+        let size = self.size + gossip.wire_size();
+        if matches!(self.max_bytes, Some(max_bytes) if size > max_bytes) {
+            return false;
+        }
+
+        self.size = size;
+        self.ids.insert(gossip.id());
+        self.last = Some(gossip);
+        true
+    }
+
+    /// check whether a gossip of the given node was already added
+    pub fn contains(&self, id: &ed25519::PublicKey) -> bool {
+        self.ids.contains(id)
+    }
+
+    /// the number of collected gossips
+    pub fn len(&self) -> usize {
+        self.gossips.len() + usize::from(self.last.is_some())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// the total number of bytes of the collected gossips
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn build(self) -> Vec<Gossip> {
+        let mut gossips = self.gossips;
+        gossips.extend(self.last);
+        gossips
+    }
+}
+
 impl<'a> GossipSlice<'a> {
     pub fn try_from_slice(slice: &'a [u8]) -> Result<Self, GossipError> {
         let info = GossipInfo::try_from_slice(slice)?;
@@ -402,6 +563,53 @@ mod tests {
         }
     }
 
+    #[test]
+    fn gossips_builder_dedup() {
+        let mut rng = Seed::from([0; Seed::SIZE]).into_rand_chacha();
+        let id = ed25519::SecretKey::new(&mut rng);
+        let address: SocketAddr = "127.0.0.1:9876".parse().unwrap();
+        let gossip = Gossip::new(address, &id, Subscriptions::new().as_slice());
+
+        let mut builder = GossipsBuilder::new();
+        assert!(builder.is_empty());
+        assert!(!builder.contains(&gossip.id()));
+
+        assert!(builder.add(gossip.clone()));
+        assert!(!builder.add(gossip.clone()));
+        assert!(builder.contains(&gossip.id()));
+        assert_eq!(builder.len(), 1);
+        assert_eq!(builder.size(), gossip.wire_size());
+        assert_eq!(builder.build(), vec![gossip]);
+    }
+
+    #[test]
+    fn gossips_builder_max() {
+        let mut rng = Seed::from([0; Seed::SIZE]).into_rand_chacha();
+        let address: SocketAddr = "127.0.0.1:9876".parse().unwrap();
+        let gossips: Vec<_> = (0..3)
+            .map(|_| {
+                let id = ed25519::SecretKey::new(&mut rng);
+                Gossip::new(address, &id, Subscriptions::new().as_slice())
+            })
+            .collect();
+
+        let mut builder = GossipsBuilder::new().with_max(2);
+        assert!(builder.add(gossips[0].clone()));
+        assert!(builder.add(gossips[1].clone()));
+        assert!(!builder.add(gossips[2].clone()));
+        assert_eq!(builder.build(), gossips[..2].to_vec());
+
+        let mut builder = GossipsBuilder::new().with_max(2);
+        assert!(builder.add_last(gossips[2].clone()));
+        assert!(builder.add(gossips[0].clone()));
+        assert!(!builder.add(gossips[1].clone()));
+        assert_eq!(builder.len(), 2);
+        assert_eq!(
+            builder.build(),
+            vec![gossips[0].clone(), gossips[2].clone()]
+        );
+    }
+
     #[test]
     fn simple_ipv4() {
         let mut rng = Seed::from([0; Seed::SIZE]).into_rand_chacha();
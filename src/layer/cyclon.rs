@@ -1,5 +1,5 @@
 use crate::{
-    layer::{Layer, ViewBuilder},
+    layer::{Layer, LayerDescription, ViewBuilder},
     InterestLevel, PriorityMap, Profile, Topic,
 };
 use keynesis::key::ed25519;
@@ -25,6 +25,10 @@ impl Layer for Cyclon {
         self.nodes.iter().for_each(|(k, _)| builder.add(k));
     }
 
+    fn view_len(&self) -> usize {
+        self.nodes.len()
+    }
+
     fn remove(&mut self, id: &ed25519::PublicKey) {
         self.nodes.pop(id);
     }
@@ -41,4 +45,8 @@ impl Layer for Cyclon {
     fn unsubscribe(&mut self, _topic: &Topic) {}
 
     fn subscriptions(&self, _output: &mut PriorityMap<InterestLevel, Topic>) {}
+
+    fn describe(&self) -> LayerDescription {
+        LayerDescription::new(self.name()).with("length", self.nodes.cap())
+    }
 }
@@ -2,17 +2,30 @@ mod cyclon;
 mod rings;
 mod vicinity;
 
-pub use self::{cyclon::Cyclon, rings::Rings, vicinity::Vicinity};
+pub use self::{
+    cyclon::Cyclon,
+    rings::Rings,
+    vicinity::{ProximityMetric, SubscriptionOverlap, Vicinity},
+};
 use crate::{InterestLevel, PriorityMap, Profile, Topic};
 use keynesis::key::ed25519;
-use std::collections::HashSet;
+use std::{collections::HashSet, fmt};
+use thiserror::Error;
 
 pub trait Layer: Send {
     fn name(&self) -> &'static str;
 
     fn view(&mut self, builder: &mut ViewBuilder);
 
-    fn remove(&mut self, id: &ed25519::PublicKey);
+    /// the number of distinct nodes the layer currently links to (and
+    /// may select in its views), without building a view
+    fn view_len(&self) -> usize {
+        0
+    }
+
+    /// called when the node is removed from the topology (demoted,
+    /// forgotten or evicted), the layer should not select it anymore
+    fn remove(&mut self, _id: &ed25519::PublicKey) {}
     fn reset(&mut self);
 
     fn subscribe(&mut self, topic: Topic);
@@ -20,6 +33,39 @@ pub trait Layer: Send {
     fn subscriptions(&self, output: &mut PriorityMap<InterestLevel, Topic>);
 
     fn populate(&mut self, our_profile: &Profile, new_profile: &Profile);
+
+    /// the effective configuration of the layer, for diagnostics
+    fn describe(&self) -> LayerDescription {
+        LayerDescription::new(self.name())
+    }
+
+    /// the internal state of the layer, to restore with
+    /// [`Layer::load_state`] after a restart. Empty for the layers with
+    /// no state worth saving (the default).
+    fn save_state(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// restore the state saved with [`Layer::save_state`], replacing the
+    /// current state of the layer
+    fn load_state(&mut self, _state: &[u8]) -> Result<(), LayerStateError> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum LayerStateError {
+    #[error("Invalid length of the layer state ({length})")]
+    InvalidLength { length: usize },
+}
+
+/// the configuration of a [`Layer`], see [`Layer::describe`]
+///
+/// Displayed as `name(parameter=value, ...)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayerDescription {
+    pub name: &'static str,
+    pub parameters: Vec<(&'static str, String)>,
 }
 
 pub trait LayerBuilder {
@@ -27,19 +73,86 @@ pub trait LayerBuilder {
     fn build_for_gossip(&self) -> Vec<Box<dyn Layer + Send + Sync>>;
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Selection {
-    Topic { topic: Topic },
+    Topic {
+        topic: Topic,
+    },
+    /// select the nodes for any of the given topics, as if selecting for
+    /// every topic and taking the union of the views
+    Topics {
+        topics: HashSet<Topic>,
+    },
+    /// select the nodes that are not subscribed to the given topic
+    Exclude {
+        topic: Topic,
+    },
     Any,
 }
 
+#[derive(Debug, Error)]
+pub enum SelectionError {
+    #[error("The selected topic ({topic}) is not one of the known topics")]
+    UnknownTopic { topic: Topic },
+}
+
 #[doc(hidden)]
 pub struct ViewBuilder {
     event_origin: Option<ed25519::PublicKey>,
 
     selection: Selection,
+    max_size: Option<usize>,
 
-    view: HashSet<ed25519::PublicKey>,
+    included: Vec<ed25519::PublicKey>,
+    view: Vec<ed25519::PublicKey>,
+}
+
+/// adds the nodes to the view of a [`ViewBuilder`] while its selection is
+/// borrowed, see [`ViewBuilder::with_selection`]
+pub(crate) struct ViewAdder<'a> {
+    origin: Option<&'a ed25519::PublicKey>,
+    view: &'a mut Vec<ed25519::PublicKey>,
+}
+
+impl<'a> ViewAdder<'a> {
+    pub(crate) fn origin(&self) -> Option<&ed25519::PublicKey> {
+        self.origin
+    }
+
+    pub(crate) fn add(&mut self, node: &ed25519::PublicKey) {
+        // the views are small (a few tens of nodes), a linear search
+        // is cheap enough and keeps the order the nodes were added in
+        if !self.view.contains(node) {
+            self.view.push(*node);
+        }
+    }
+}
+
+impl LayerDescription {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            parameters: Vec::new(),
+        }
+    }
+
+    pub fn with(mut self, parameter: &'static str, value: impl ToString) -> Self {
+        self.parameters.push((parameter, value.to_string()));
+        self
+    }
+}
+
+impl fmt::Display for LayerDescription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}(", self.name)?;
+        for (index, (parameter, value)) in self.parameters.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}={}", parameter, value)?;
+        }
+        write!(f, ")")
+    }
 }
 
 impl ViewBuilder {
@@ -47,7 +160,35 @@ impl ViewBuilder {
         Self {
             event_origin: None,
             selection,
-            view: HashSet::new(),
+            max_size: None,
+            included: Vec::new(),
+            view: Vec::new(),
+        }
+    }
+
+    /// create a `ViewBuilder` for the given selection, checking the selected
+    /// topic (if any) is one of the `known_topics`.
+    ///
+    /// Selecting a topic no layer knows about would silently build an empty
+    /// view, use this function to detect it.
+    pub fn try_new(
+        selection: Selection,
+        known_topics: &HashSet<Topic>,
+    ) -> Result<Self, SelectionError> {
+        match selection {
+            Selection::Topic { topic } if !known_topics.contains(&topic) => {
+                Err(SelectionError::UnknownTopic { topic })
+            }
+            Selection::Topics { ref topics } => {
+                if let Some(topic) = topics.iter().find(|topic| !known_topics.contains(topic)) {
+                    Err(SelectionError::UnknownTopic { topic: *topic })
+                } else {
+                    Ok(Self::new(selection))
+                }
+            }
+            Selection::Topic { .. } | Selection::Exclude { .. } | Selection::Any => {
+                Ok(Self::new(selection))
+            }
         }
     }
 
@@ -56,19 +197,237 @@ impl ViewBuilder {
         self
     }
 
+    /// limit the number of nodes of the view. The nodes added with
+    /// [`ViewBuilder::must_include`] are kept first, then the nodes in
+    /// the order the layers added them.
+    pub fn with_max_size(&mut self, max_size: usize) -> &mut Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
     pub fn origin(&self) -> Option<&ed25519::PublicKey> {
         self.event_origin.as_ref()
     }
 
-    pub fn selection(&self) -> Selection {
-        self.selection
+    pub fn selection(&self) -> &Selection {
+        &self.selection
+    }
+
+    /// call `f` with the selection and a [`ViewAdder`], so the layers can
+    /// add nodes while matching on the selection without cloning it
+    pub(crate) fn with_selection<F>(&mut self, f: F)
+    where
+        F: FnOnce(&Selection, &mut ViewAdder<'_>),
+    {
+        let mut adder = ViewAdder {
+            origin: self.event_origin.as_ref(),
+            view: &mut self.view,
+        };
+        f(&self.selection, &mut adder);
     }
 
     pub fn add(&mut self, node: &ed25519::PublicKey) {
-        self.view.insert(*node);
+        ViewAdder {
+            origin: self.event_origin.as_ref(),
+            view: &mut self.view,
+        }
+        .add(node)
     }
 
-    pub(crate) fn build(self) -> HashSet<ed25519::PublicKey> {
-        self.view
+    /// always include the given node in the view, whatever the layers
+    /// select and even if the view is limited (see
+    /// [`ViewBuilder::with_max_size`]). The origin of the event and the
+    /// nodes that are not known peers are still excluded.
+    pub fn must_include(&mut self, node: ed25519::PublicKey) -> &mut Self {
+        if !self.included.contains(&node) {
+            self.included.push(node);
+        }
+        self
+    }
+
+    /// the view, without the origin of the event (see
+    /// [`ViewBuilder::with_origin`]) so the event is not sent back to it
+    ///
+    /// The order is deterministic: the nodes selected by the layers
+    /// sorted by id (lexicographically over the bytes of the keys), then
+    /// the nodes added with [`ViewBuilder::must_include`] in the order
+    /// they were added.
+    pub(crate) fn build(self) -> Vec<ed25519::PublicKey> {
+        self.build_filtered(|_, _| true)
+    }
+
+    /// like [`ViewBuilder::build`] but only keeps the nodes `lookup`
+    /// resolves to a profile. With [`Selection::Exclude`] the nodes
+    /// subscribed to the excluded topic are removed too, unless they were
+    /// forced in the view (see [`ViewBuilder::must_include`]).
+    ///
+    /// The nodes are filtered before the view is limited to its max size,
+    /// so the filtered out nodes do not take the place of the other ones.
+    pub(crate) fn build_with<'a, F>(self, mut lookup: F) -> Vec<ed25519::PublicKey>
+    where
+        F: FnMut(&ed25519::PublicKey) -> Option<&'a Profile>,
+    {
+        let excluded = if let Selection::Exclude { topic } = &self.selection {
+            Some(*topic)
+        } else {
+            None
+        };
+
+        self.build_filtered(|node, forced| match lookup(node) {
+            None => false,
+            Some(profile) => {
+                forced || !matches!(excluded, Some(topic) if profile.is_subscribed(&topic))
+            }
+        })
+    }
+
+    /// build the view with the nodes `is_valid` accepts, it is given the
+    /// node and whether it was forced in the view
+    fn build_filtered<F>(self, mut is_valid: F) -> Vec<ed25519::PublicKey>
+    where
+        F: FnMut(&ed25519::PublicKey, bool) -> bool,
+    {
+        let max_size = self.max_size.unwrap_or(usize::MAX);
+        let origin = self.event_origin;
+        let is_origin = |node: &ed25519::PublicKey| Some(*node) == origin;
+
+        let included: Vec<_> = self
+            .included
+            .into_iter()
+            .filter(|node| !is_origin(node) && is_valid(node, true))
+            .collect();
+
+        let mut view: Vec<_> = self
+            .view
+            .into_iter()
+            .filter(|node| !is_origin(node) && !included.contains(node) && is_valid(node, false))
+            .take(max_size.saturating_sub(included.len()))
+            .collect();
+        view.sort();
+
+        view.extend(included);
+        view
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::{Arbitrary, Gen};
+    use std::cmp::Ordering;
+
+    #[derive(Debug, Clone, Copy)]
+    struct Id(ed25519::PublicKey);
+
+    impl Arbitrary for Id {
+        fn arbitrary(g: &mut Gen) -> Self {
+            let mut bytes = [0; ed25519::PublicKey::SIZE];
+            bytes.iter_mut().for_each(|byte| {
+                *byte = u8::arbitrary(g);
+            });
+            Self(ed25519::PublicKey::from(bytes))
+        }
+    }
+
+    /// the views and the rings rely on the ids being ordered
+    /// lexicographically over the bytes of the keys
+    #[quickcheck]
+    fn id_order_is_lexicographic(a: Id, b: Id) -> bool {
+        a.0.cmp(&b.0) == a.0.as_ref().cmp(b.0.as_ref())
+    }
+
+    #[quickcheck]
+    fn id_order_consistent_with_eq(a: Id, b: Id) -> bool {
+        (a.0.cmp(&b.0) == Ordering::Equal) == (a.0 == b.0)
+            && a.0.cmp(&b.0) == b.0.cmp(&a.0).reverse()
+    }
+
+    fn id(seed: u8) -> ed25519::PublicKey {
+        crate::testing::TestTopology::secret_key(seed).public_key()
+    }
+
+    #[test]
+    fn view_builder_max_size() {
+        let mut builder = ViewBuilder::new(Selection::Any);
+        builder.with_max_size(3);
+        builder.must_include(id(1));
+        for seed in 2..=5 {
+            builder.add(&id(seed));
+        }
+        builder.add(&id(2));
+
+        let expected: HashSet<_> = (1..=3).map(id).collect();
+        assert_eq!(
+            builder.build().into_iter().collect::<HashSet<_>>(),
+            expected
+        );
+
+        let mut builder = ViewBuilder::new(Selection::Any);
+        builder.with_max_size(1);
+        builder.must_include(id(1)).must_include(id(2));
+        builder.add(&id(3));
+
+        let expected: HashSet<_> = (1..=2).map(id).collect();
+        assert_eq!(
+            builder.build().into_iter().collect::<HashSet<_>>(),
+            expected
+        );
+    }
+
+    #[test]
+    fn view_builder_excludes_origin() {
+        let mut builder = ViewBuilder::new(Selection::Any);
+        builder.with_origin(id(1));
+        builder.with_max_size(2);
+        builder.must_include(id(1));
+        for seed in 1..=3 {
+            builder.add(&id(seed));
+        }
+
+        let expected: HashSet<_> = (2..=3).map(id).collect();
+        assert_eq!(
+            builder.build().into_iter().collect::<HashSet<_>>(),
+            expected
+        );
+    }
+
+    #[test]
+    fn view_builder_order() {
+        let mut ids: Vec<_> = (1..=5).map(id).collect();
+
+        let mut builder = ViewBuilder::new(Selection::Any);
+        builder.must_include(ids[4]).must_include(ids[3]);
+        for id in ids.iter().take(3).rev() {
+            builder.add(id);
+        }
+        let view = builder.build();
+
+        let (forced, selected) = (ids.split_off(3), ids);
+        let mut expected = selected;
+        expected.sort();
+        expected.extend(forced.iter().rev());
+        assert_eq!(view, expected);
+    }
+
+    #[test]
+    fn view_builder_unknown_topic() {
+        let known = Topic::new([1; Topic::SIZE]);
+        let unknown = Topic::new([2; Topic::SIZE]);
+        let known_topics: HashSet<Topic> = std::iter::once(known).collect();
+
+        assert!(ViewBuilder::try_new(Selection::Any, &known_topics).is_ok());
+        assert!(ViewBuilder::try_new(Selection::Topic { topic: known }, &known_topics).is_ok());
+        assert!(matches!(
+            ViewBuilder::try_new(Selection::Topic { topic: unknown }, &known_topics),
+            Err(SelectionError::UnknownTopic { topic }) if topic == unknown
+        ));
+
+        let topics = vec![known, unknown].into_iter().collect();
+        assert!(matches!(
+            ViewBuilder::try_new(Selection::Topics { topics }, &known_topics),
+            Err(SelectionError::UnknownTopic { topic }) if topic == unknown
+        ));
+        let topics = known_topics.clone();
+        assert!(ViewBuilder::try_new(Selection::Topics { topics }, &known_topics).is_ok());
     }
 }
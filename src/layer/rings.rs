@@ -1,9 +1,9 @@
 use crate::{
-    layer::{Layer, Selection, ViewBuilder},
+    layer::{Layer, LayerDescription, Selection, ViewAdder, ViewBuilder},
     InterestLevel, PriorityMap, Profile, Subscription, Subscriptions, Topic,
 };
 use keynesis::key::ed25519;
-use std::cmp::Ordering;
+use std::{cmp::Ordering, collections::HashSet};
 
 struct Ring {
     length: u8,
@@ -49,8 +49,8 @@ impl Ring {
         InterestLevel::new(level)
     }
 
-    pub fn recipients(&mut self, builder: &mut ViewBuilder) {
-        let (predecessor, successor) = if let Some(from) = builder.origin() {
+    pub fn recipients(&mut self, adder: &mut ViewAdder<'_>) {
+        let (predecessor, successor) = if let Some(from) = adder.origin() {
             (
                 !self.predecessors.contains(from),
                 !self.successors.contains(from),
@@ -61,14 +61,14 @@ impl Ring {
 
         if predecessor {
             if let Some((key, ())) = self.predecessors.pop_lru() {
-                builder.add(&key);
+                adder.add(&key);
                 self.predecessors.put(key, ());
             }
         }
 
         if successor {
             if let Some((key, ())) = self.successors.pop_lru() {
-                builder.add(&key);
+                adder.add(&key);
                 self.successors.put(key, ());
             }
         }
@@ -151,15 +151,15 @@ impl Rings {
         subscriptions
     }
 
-    fn recipients_for_event(&mut self, topic: &Topic, builder: &mut ViewBuilder) {
+    fn recipients_for_event(&mut self, topic: &Topic, adder: &mut ViewAdder<'_>) {
         if let Some(ring) = self.links.get_mut(topic) {
-            ring.recipients(builder);
+            ring.recipients(adder);
         }
     }
 
-    fn recipients_for_all(&mut self, builder: &mut ViewBuilder) {
+    fn recipients_for_all(&mut self, adder: &mut ViewAdder<'_>) {
         for (_, ring) in self.links.iter_mut() {
-            ring.recipients(builder);
+            ring.recipients(adder);
         }
     }
 
@@ -183,14 +183,33 @@ impl Layer for Rings {
     }
 
     fn view(&mut self, builder: &mut ViewBuilder) {
-        match builder.selection() {
+        builder.with_selection(|selection, adder| match selection {
             Selection::Any => {
-                self.recipients_for_all(builder);
+                self.recipients_for_all(adder);
             }
             Selection::Topic { topic } => {
-                self.recipients_for_event(&topic, builder);
+                self.recipients_for_event(topic, adder);
             }
-        }
+            Selection::Topics { topics } => {
+                for topic in topics.iter() {
+                    self.recipients_for_event(topic, adder);
+                }
+            }
+            Selection::Exclude { topic } => {
+                for (_, ring) in self.links.iter_mut().filter(|(t, _)| *t != topic) {
+                    ring.recipients(adder);
+                }
+            }
+        })
+    }
+
+    fn view_len(&self) -> usize {
+        self.links
+            .iter()
+            .flat_map(|(_, ring)| ring.predecessors.iter().chain(ring.successors.iter()))
+            .map(|(id, _)| id)
+            .collect::<HashSet<_>>()
+            .len()
     }
 
     fn remove(&mut self, id: &ed25519::PublicKey) {
@@ -231,4 +250,46 @@ impl Layer for Rings {
             output.put(interest_level, *topic);
         }
     }
+
+    fn describe(&self) -> LayerDescription {
+        LayerDescription::new(self.name()).with("length", self.length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestTopology;
+
+    #[test]
+    fn select_topics() {
+        let topics: Vec<Topic> = (1..=3).map(|i| Topic::new([i; Topic::SIZE])).collect();
+        let level = InterestLevel::new(1);
+
+        let our_profile = TestTopology::peer(
+            TestTopology::OUR_SEED,
+            &topics
+                .iter()
+                .map(|topic| (*topic, level))
+                .collect::<Vec<_>>(),
+        );
+        let peers: Vec<_> = (1..=3)
+            .map(|seed| TestTopology::peer(seed, &[(topics[seed as usize - 1], level)]))
+            .collect();
+        let ids: Vec<_> = peers.iter().map(|peer| peer.id()).collect();
+        let topology = TestTopology::new_with(our_profile, peers);
+
+        let mut rings = Rings::new(2);
+        assert_eq!(rings.view_len(), 0);
+        topology.populate_for(&mut rings, topology.profile());
+        assert_eq!(rings.view_len(), 3);
+
+        let view = topology.view(&mut rings, Selection::Topic { topic: topics[0] });
+        assert_eq!(view, std::iter::once(ids[0]).collect());
+
+        let selected = vec![topics[0], topics[1]].into_iter().collect();
+        let view = topology.view(&mut rings, Selection::Topics { topics: selected });
+        let expected: HashSet<_> = vec![ids[0], ids[1]].into_iter().collect();
+        assert_eq!(view, expected);
+    }
 }
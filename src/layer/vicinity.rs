@@ -1,20 +1,83 @@
 use crate::{
-    layer::{Layer, ViewBuilder},
-    profile::Proximity,
-    InterestLevel, PriorityMap, Profile, Topic,
+    layer::{Layer, LayerDescription, LayerStateError, ViewBuilder},
+    InterestLevel, PriorityMap, Profile, Proximity, Topic,
 };
 use keynesis::key::ed25519;
+use std::convert::{TryFrom as _, TryInto as _};
+
+/// compute how close a profile is to ours, see [`Vicinity::with_metric`]
+pub trait ProximityMetric {
+    fn proximity(&self, our_profile: &Profile, their_profile: &Profile) -> Proximity;
+}
+
+/// the default metric of the [`Vicinity`]: the overlap of the
+/// subscriptions, see [`Profile::proximity_to`]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SubscriptionOverlap;
 
 pub struct Vicinity {
     nodes: PriorityMap<Proximity, ed25519::PublicKey>,
+    min_proximity: Option<Proximity>,
+    topic_filter: bool,
+    metric: Box<dyn ProximityMetric + Send + Sync>,
+}
+
+impl ProximityMetric for SubscriptionOverlap {
+    fn proximity(&self, our_profile: &Profile, their_profile: &Profile) -> Proximity {
+        our_profile.proximity_to(their_profile)
+    }
+}
+
+impl<F> ProximityMetric for F
+where
+    F: Fn(&Profile, &Profile) -> Proximity,
+{
+    fn proximity(&self, our_profile: &Profile, their_profile: &Profile) -> Proximity {
+        self(our_profile, their_profile)
+    }
 }
 
 impl Vicinity {
+    /// the size of a node in the saved state: the priority and the
+    /// proximity (big endian `u64`) followed by the id
+    const STATE_ENTRY_SIZE: usize = 8 + 8 + ed25519::PublicKey::SIZE;
+
     pub fn new(length: usize) -> Self {
         Self {
             nodes: PriorityMap::new(length),
+            min_proximity: None,
+            topic_filter: false,
+            metric: Box::new(SubscriptionOverlap),
         }
     }
+
+    /// select the nodes with the given metric instead of the overlap of
+    /// the subscriptions ([`SubscriptionOverlap`])
+    pub fn with_metric<M>(mut self, metric: M) -> Self
+    where
+        M: ProximityMetric + Send + Sync + 'static,
+    {
+        self.metric = Box::new(metric);
+        self
+    }
+
+    /// ignore the profiles that are not at least as close as the given
+    /// proximity, even if it means keeping less than `length` nodes.
+    pub fn with_min_proximity(mut self, min_proximity: Proximity) -> Self {
+        self.min_proximity = Some(min_proximity);
+        self
+    }
+
+    /// ignore the profiles that do not share any topic with the profile
+    /// the view is built for (the recipient of the gossips for the gossip
+    /// layers), whatever their proximity. Disabled by default.
+    ///
+    /// If that profile is not subscribed to any topic, no profile is
+    /// ignored.
+    pub fn with_topic_filter(mut self, topic_filter: bool) -> Self {
+        self.topic_filter = topic_filter;
+        self
+    }
 }
 
 impl Layer for Vicinity {
@@ -26,6 +89,10 @@ impl Layer for Vicinity {
         self.nodes.iter().for_each(|(_, v)| builder.add(v));
     }
 
+    fn view_len(&self) -> usize {
+        self.nodes.len()
+    }
+
     fn remove(&mut self, id: &ed25519::PublicKey) {
         self.nodes.remove(id);
     }
@@ -34,7 +101,21 @@ impl Layer for Vicinity {
     }
 
     fn populate(&mut self, our_profile: &Profile, new_profile: &Profile) {
-        let proximity = our_profile.proximity_to(new_profile);
+        if self.topic_filter {
+            let mut topics = our_profile.topics().peekable();
+            if topics.peek().is_some() && !topics.any(|topic| new_profile.is_subscribed(topic)) {
+                return;
+            }
+        }
+
+        let proximity = self.metric.proximity(our_profile, new_profile);
+
+        if let Some(min_proximity) = self.min_proximity {
+            if proximity < min_proximity {
+                return;
+            }
+        }
+
         self.nodes.put(proximity, new_profile.id());
     }
 
@@ -43,4 +124,199 @@ impl Layer for Vicinity {
     fn unsubscribe(&mut self, _: &Topic) {}
 
     fn subscriptions(&self, _output: &mut PriorityMap<InterestLevel, Topic>) {}
+
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = Vec::with_capacity(self.nodes.len() * Self::STATE_ENTRY_SIZE);
+        for (proximity, id) in self.nodes.iter() {
+            state.extend_from_slice(&(proximity.priority() as u64).to_be_bytes());
+            state.extend_from_slice(&(proximity.proximity() as u64).to_be_bytes());
+            state.extend_from_slice(id.as_ref());
+        }
+        state
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), LayerStateError> {
+        if state.len() % Self::STATE_ENTRY_SIZE != 0 {
+            return Err(LayerStateError::InvalidLength {
+                length: state.len(),
+            });
+        }
+
+        self.nodes.clear();
+        // the last put of the nodes of equal priority comes first, put them
+        // back in reverse to keep their order
+        for entry in state.chunks_exact(Self::STATE_ENTRY_SIZE).rev() {
+            let priority = u64::from_be_bytes(entry[..8].try_into().expect("8 bytes"));
+            let proximity = u64::from_be_bytes(entry[8..16].try_into().expect("8 bytes"));
+            let id = ed25519::PublicKey::try_from(&entry[16..]).expect("valid public key size");
+
+            self.nodes
+                .put(Proximity::new(priority as usize, proximity as usize), id);
+        }
+
+        Ok(())
+    }
+
+    fn describe(&self) -> LayerDescription {
+        let mut description = LayerDescription::new(self.name()).with("length", self.nodes.cap());
+        if self.topic_filter {
+            description = description.with("topic_filter", true);
+        }
+        if let Some(min_proximity) = self.min_proximity {
+            description
+                .with("min_priority", min_proximity.priority())
+                .with("min_proximity", min_proximity.proximity())
+        } else {
+            description
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{layer::Selection, testing::TestTopology};
+
+    #[test]
+    fn save_load_state() {
+        let topic = Topic::new([1; Topic::SIZE]);
+        let level = InterestLevel::new(1);
+
+        let topology = TestTopology::new_with(
+            TestTopology::peer(TestTopology::OUR_SEED, &[(topic, level)]),
+            vec![
+                TestTopology::peer(1, &[(topic, level)]),
+                TestTopology::peer(2, &[]),
+                TestTopology::peer(3, &[(topic, InterestLevel::new(2))]),
+            ],
+        );
+        let mut vicinity = Vicinity::new(20);
+        topology.populate(&mut vicinity);
+        let state = vicinity.save_state();
+
+        let mut restored = Vicinity::new(20);
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.save_state(), state);
+        assert_eq!(
+            restored.nodes.iter().collect::<Vec<_>>(),
+            vicinity.nodes.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            topology.view(&mut restored, Selection::Any),
+            topology.view(&mut vicinity, Selection::Any)
+        );
+
+        assert_eq!(restored.view_len(), 3);
+        assert_eq!(Vicinity::new(20).view_len(), 0);
+
+        assert!(matches!(
+            restored.load_state(&state[1..]),
+            Err(LayerStateError::InvalidLength { .. })
+        ));
+    }
+
+    #[test]
+    fn custom_metric() {
+        let topic = Topic::new([1; Topic::SIZE]);
+        let level = InterestLevel::new(1);
+
+        let topology = TestTopology::new_with(
+            TestTopology::peer(TestTopology::OUR_SEED, &[(topic, level)]),
+            vec![
+                TestTopology::peer(1, &[(topic, level)]),
+                TestTopology::peer(2, &[]),
+            ],
+        );
+        let by_port =
+            |_: &Profile, their: &Profile| Proximity::new(their.address().port() as usize, 0);
+
+        let mut vicinity = Vicinity::new(1);
+        topology.populate(&mut vicinity);
+        let view = topology.view(&mut vicinity, Selection::Any);
+        assert_eq!(view, std::iter::once(topology.peers()[0].id()).collect());
+
+        let mut vicinity = Vicinity::new(1).with_metric(by_port);
+        topology.populate(&mut vicinity);
+        let view = topology.view(&mut vicinity, Selection::Any);
+        assert_eq!(view, std::iter::once(topology.peers()[1].id()).collect());
+    }
+
+    #[test]
+    fn describe() {
+        let vicinity = Vicinity::new(20).with_min_proximity(Proximity::new(2, 1));
+
+        assert_eq!(
+            vicinity.describe().to_string(),
+            "poldercast::vicinity(length=20, min_priority=2, min_proximity=1)"
+        );
+        assert_eq!(
+            Vicinity::new(20).describe().to_string(),
+            "poldercast::vicinity(length=20)"
+        );
+    }
+
+    #[test]
+    fn min_proximity() {
+        let common = Topic::new([1; Topic::SIZE]);
+        let other = Topic::new([2; Topic::SIZE]);
+        let level = InterestLevel::new(1);
+
+        let our_profile = TestTopology::peer(TestTopology::OUR_SEED, &[(common, level)]);
+        let close = TestTopology::peer(1, &[(common, level)]);
+        let close_id = close.id();
+        let topology = TestTopology::new_with(
+            our_profile,
+            vec![
+                close,
+                TestTopology::peer(2, &[(other, level)]),
+                TestTopology::peer(3, &[]),
+            ],
+        );
+
+        let mut vicinity = Vicinity::new(10);
+        topology.populate(&mut vicinity);
+        assert_eq!(topology.view(&mut vicinity, Selection::Any).len(), 3);
+
+        let mut vicinity = Vicinity::new(10).with_min_proximity(Proximity::new(0, 1));
+        topology.populate(&mut vicinity);
+        let view = topology.view(&mut vicinity, Selection::Any);
+        assert_eq!(view.len(), 1);
+        assert!(view.contains(&close_id));
+
+        let mut vicinity = Vicinity::new(10).with_min_proximity(Proximity::new(10, 1));
+        topology.populate(&mut vicinity);
+        assert!(topology.view(&mut vicinity, Selection::Any).is_empty());
+    }
+
+    #[test]
+    fn topic_filter() {
+        let common = Topic::new([1; Topic::SIZE]);
+        let other = Topic::new([2; Topic::SIZE]);
+        let level = InterestLevel::new(1);
+
+        let peers = || {
+            vec![
+                TestTopology::peer(1, &[(common, level)]),
+                TestTopology::peer(2, &[(other, level)]),
+                TestTopology::peer(3, &[]),
+            ]
+        };
+        let close_id = TestTopology::peer(1, &[]).id();
+
+        let topology = TestTopology::new_with(
+            TestTopology::peer(TestTopology::OUR_SEED, &[(common, level)]),
+            peers(),
+        );
+        let mut vicinity = Vicinity::new(10).with_topic_filter(true);
+        topology.populate(&mut vicinity);
+        let view = topology.view(&mut vicinity, Selection::Any);
+        assert_eq!(view.len(), 1);
+        assert!(view.contains(&close_id));
+
+        let unsubscribed =
+            TestTopology::new_with(TestTopology::peer(TestTopology::OUR_SEED, &[]), peers());
+        let mut vicinity = Vicinity::new(10).with_topic_filter(true);
+        unsubscribed.populate(&mut vicinity);
+        assert_eq!(unsubscribed.view(&mut vicinity, Selection::Any).len(), 3);
+    }
 }
@@ -7,17 +7,25 @@ pub mod layer;
 mod priority_map;
 mod profile;
 mod profiles;
+mod shared;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 mod topic;
 mod topology;
 
 pub use self::{
-    gossip::{Gossip, GossipError, GossipSlice},
+    gossip::{Gossip, GossipError, GossipSlice, GossipsBuilder},
     priority_map::PriorityMap,
-    profile::Profile,
-    profiles::Profiles,
+    profile::{Profile, ProfileBuilder, ProfileBuilderError, Proximity},
+    profiles::{
+        AccessStats, AvailableIter, Bucket, BucketCounts, CapacityError, CountsDelta,
+        EvictionPolicy, GcOptions, GcReport, GossipRecord, PressureWarning, Profiles, PutReport,
+        Subnet, Transition, TransitionHook,
+    },
+    shared::SharedProfiles,
     topic::{
         InterestLevel, Subscription, SubscriptionError, SubscriptionIter, SubscriptionSlice,
         Subscriptions, SubscriptionsSlice, Topic,
     },
-    topology::Topology,
+    topology::{DemotionBreakerHook, ImportStateError, IngestReport, Topology, TopologyState},
 };
@@ -65,6 +65,11 @@ where
         self.by_value.capacity()
     }
 
+    /// the maximum number of entries of the map
+    pub fn cap(&self) -> usize {
+        self.cap
+    }
+
     pub fn len(&self) -> usize {
         self.by_value.len()
     }
@@ -125,6 +130,10 @@ where
     }
 
     pub fn put(&mut self, key: K, value: V) {
+        // remove the previous version of the value first, the maps keys
+        // point into the entry so it cannot be replaced in place
+        self.remove(&value);
+
         // check if we have reached the cap
         if self.len() >= self.cap {
             // if we do check that we are not adding an entry that is lower bound
@@ -150,21 +159,7 @@ where
         let keyref: *mut V = unsafe { &mut (*entry_ptr).value };
         let v = KeyRef { k: keyref };
 
-        if let Some(mut prev) = self.by_value.insert(v, entry) {
-            let keyref: *mut V = &mut prev.value;
-            let v = KeyRef { k: keyref };
-
-            // if we have updated the priority of the value we need to also change it
-            // in the previous version of the `by_priority`
-            if let btree_map::Entry::Occupied(mut occupied) = self.by_priority.entry(k) {
-                occupied.get_mut().pop(&v);
-
-                // make sure we don't keep empty priority entries
-                if occupied.get().is_empty() {
-                    occupied.remove();
-                }
-            }
-        }
+        self.by_value.insert(v, entry);
         self.by_priority
             .entry(k)
             .or_insert_with(lru::LruCache::unbounded)
@@ -253,7 +248,7 @@ impl<K> Borrow<K> for KeyRef<K> {
 
 impl<K> Clone for KeyRef<K> {
     fn clone(&self) -> Self {
-        Self { k: self.k }
+        *self
     }
 }
 impl<K> Copy for KeyRef<K> {}
@@ -283,6 +278,21 @@ mod tests {
         assert!(map.contains(&"entry".to_owned()));
     }
 
+    #[test]
+    fn update_priority() {
+        let mut map = PriorityMap::<u32, String>::new(3);
+        map.put(1, "1".to_owned());
+        map.put(2, "2".to_owned());
+        map.put(2, "1".to_owned());
+        map.put(3, "2".to_owned());
+
+        assert_eq!(map.len(), 2);
+        let mut iter = map.iter();
+        assert_eq!(iter.next(), Some((&3u32, &"2".to_owned())));
+        assert_eq!(iter.next(), Some((&2u32, &"1".to_owned())));
+        assert_eq!(iter.next(), None);
+    }
+
     #[test]
     fn ignoring_lower_than_lower_bound() {
         let mut map = PriorityMap::<u32, String>::new(5);
@@ -4,12 +4,68 @@ use crate::{
 };
 use keynesis::{key::ed25519, passport::block::Time};
 use std::net::SocketAddr;
+use thiserror::Error;
 
 pub struct Profile {
     subscriptions: PriorityMap<InterestLevel, Topic>,
     gossip: Gossip,
 }
 
+/// builder to construct a [`Profile`] and its signed [`Gossip`]
+///
+/// The `id` and the `address` are required, the subscriptions are optional.
+///
+/// ```
+/// use keynesis::{key::ed25519, passport::block::Time, Seed};
+/// use poldercast::{InterestLevel, Profile, Topic};
+///
+/// let mut rng = Seed::from([0; Seed::SIZE]).into_rand_chacha();
+/// let id = ed25519::SecretKey::new(&mut rng);
+///
+/// let profile = Profile::builder()
+///     .id(&id)
+///     .address("127.0.0.1:9876".parse().unwrap())
+///     .subscribe(Topic::new([1; Topic::SIZE]), InterestLevel::new(1))
+///     .subscribe(Topic::new([2; Topic::SIZE]), InterestLevel::new(2))
+///     .version(Time::from(42))
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(profile.id(), id.public_key());
+/// assert_eq!(profile.subscriptions().iter().count(), 2);
+/// assert_eq!(profile.last_update(), Time::from(42));
+/// ```
+pub struct ProfileBuilder<'a> {
+    id: Option<&'a ed25519::SecretKey>,
+    address: Option<SocketAddr>,
+    version: Option<Time>,
+    subscriptions: PriorityMap<InterestLevel, Topic>,
+}
+
+fn to_subscriptions(subscriptions: &PriorityMap<InterestLevel, Topic>) -> Subscriptions {
+    let mut output = Subscriptions::new();
+    for (interest_level, topic) in subscriptions.iter() {
+        let sub = Subscription::new(*topic, *interest_level);
+        output
+            .push(sub.as_slice())
+            .expect("We are already limiting the number of internal subscriptions");
+    }
+    output
+}
+
+#[derive(Debug, Error)]
+pub enum ProfileBuilderError {
+    #[error("The profile's id is required")]
+    MissingId,
+
+    #[error("The profile's address is required")]
+    MissingAddress,
+}
+
+/// how close the interests of two profiles are, see [`Profile::proximity_to`]
+///
+/// Proximities are ordered by their priority first (the sum of the interest
+/// levels of the common topics) and then by the number of common topics.
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub struct Proximity {
     priority: usize,
@@ -26,6 +82,10 @@ impl Profile {
         }
     }
 
+    pub fn builder<'a>() -> ProfileBuilder<'a> {
+        ProfileBuilder::new()
+    }
+
     pub fn from_gossip(gossip: Gossip) -> Self {
         let mut subscriptions = PriorityMap::new(Subscriptions::MAX_NUM_SUBSCRIPTIONS);
 
@@ -73,19 +133,26 @@ impl Profile {
         self.gossip.time()
     }
 
+    /// check whether the profile was updated after the other one, a
+    /// profile updated at the same time is not newer
+    pub fn is_newer_than(&self, other: &Self) -> bool {
+        self.last_update() > other.last_update()
+    }
+
     pub fn address(&self) -> SocketAddr {
         self.gossip.address()
     }
 
     pub fn subscriptions(&self) -> Subscriptions {
-        let mut subscriptions = Subscriptions::new();
-        for (interest_level, topic) in self.subscriptions.iter() {
-            let sub = Subscription::new(*topic, *interest_level);
-            subscriptions
-                .push(sub.as_slice())
-                .expect("We are already limiting the number of internal subscriptions");
-        }
-        subscriptions
+        to_subscriptions(&self.subscriptions)
+    }
+
+    pub fn is_subscribed(&self, topic: &Topic) -> bool {
+        self.subscriptions.contains(topic)
+    }
+
+    pub(crate) fn topics(&self) -> impl Iterator<Item = &Topic> {
+        self.subscriptions.iter().map(|(_, topic)| topic)
     }
 
     pub fn proximity_to(&self, to: &Self) -> Proximity {
@@ -104,6 +171,73 @@ impl Profile {
     }
 }
 
+impl<'a> ProfileBuilder<'a> {
+    pub fn new() -> Self {
+        Self {
+            id: None,
+            address: None,
+            version: None,
+            subscriptions: PriorityMap::new(Subscriptions::MAX_NUM_SUBSCRIPTIONS),
+        }
+    }
+
+    /// set the key used to identify the profile and to sign its gossip
+    pub fn id(mut self, id: &'a ed25519::SecretKey) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    pub fn address(mut self, address: SocketAddr) -> Self {
+        self.address = Some(address);
+        self
+    }
+
+    /// set the time of the gossip, which is the version of the profile (see
+    /// [`Profile::is_newer_than`]). Defaults to the current time.
+    pub fn version(mut self, version: Time) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    /// add the topic to the profile's subscriptions. If the topic was
+    /// already subscribed the interest level is updated.
+    pub fn subscribe(mut self, topic: Topic, interest_level: InterestLevel) -> Self {
+        self.subscriptions.put(interest_level, topic);
+        self
+    }
+
+    pub fn build(self) -> Result<Profile, ProfileBuilderError> {
+        let id = self.id.ok_or(ProfileBuilderError::MissingId)?;
+        let address = self.address.ok_or(ProfileBuilderError::MissingAddress)?;
+
+        let version = self.version.unwrap_or_else(Time::now);
+        let subscriptions = to_subscriptions(&self.subscriptions);
+
+        Ok(Profile {
+            gossip: Gossip::new_at(address, id, subscriptions.as_slice(), version),
+            subscriptions: self.subscriptions,
+        })
+    }
+}
+
+impl Proximity {
+    pub const fn new(priority: usize, proximity: usize) -> Self {
+        Self {
+            priority,
+            proximity,
+        }
+    }
+
+    pub fn priority(&self) -> usize {
+        self.priority
+    }
+
+    /// the number of common topics
+    pub fn proximity(&self) -> usize {
+        self.proximity
+    }
+}
+
 impl PartialOrd<Self> for Proximity {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         Some(self.cmp(other))
@@ -127,6 +261,12 @@ impl Ord for Proximity {
     }
 }
 
+impl<'a> Default for ProfileBuilder<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl From<Gossip> for Profile {
     fn from(gossip: Gossip) -> Self {
         Self::from_gossip(gossip)
@@ -1,20 +1,308 @@
-use crate::Profile;
-use keynesis::key::ed25519;
+use crate::{Gossip, Profile, Topic};
+use keynesis::{key::ed25519, passport::block::Time};
 use lru::LruCache;
-use std::sync::Arc;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    convert::TryInto as _,
+    net::IpAddr,
+    sync::{
+        atomic::{self, AtomicU64},
+        Arc,
+    },
+    time::Duration,
+};
+use thiserror::Error;
+
+/// the different buckets a [`Profile`] can be in within the [`Profiles`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Bucket {
+    /// the profiles that have been demoted
+    Dirty,
+    /// the profiles we know about but we did not promote yet
+    Pool,
+    /// the profiles that have been promoted
+    Trusted,
+}
+
+/// the strategy used to select the entry to remove when one of the
+/// buckets of the [`Profiles`] is full
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum EvictionPolicy {
+    /// remove the least recently used entry
+    Lru,
+    /// like [`EvictionPolicy::Lru`] but every entry that has been referenced
+    /// (see [`Profiles::get`]) since it was last examined for eviction is
+    /// given a second chance: its reference is cleared and it is moved back
+    /// as the most recently used entry instead of being removed.
+    SecondChance,
+}
+
+/// configure the housekeeping operations run by [`Profiles::gc`]
+///
+/// All the operations are disabled by default.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct GcOptions {
+    /// forget the demoted profiles that have not been updated for at
+    /// least the given duration
+    pub forget_dirty_after: Option<Duration>,
+    /// forget the profiles of the pool (not demoted and not promoted)
+    /// that have not been updated for at least the given duration
+    pub forget_stale_after: Option<Duration>,
+    /// move back to the pool the profiles demoted for at least the given
+    /// duration, see [`Profiles::expired_quarantine`]
+    pub lift_quarantine_after: Option<Duration>,
+}
+
+/// the result of [`Profiles::gc`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GcReport {
+    pub forgotten_dirty: Vec<ed25519::PublicKey>,
+    pub forgotten_stale: Vec<ed25519::PublicKey>,
+    pub quarantine_lifted: Vec<ed25519::PublicKey>,
+}
+
+/// iterator over the available profiles (the promoted and the pooled ones)
+/// of the [`Profiles`], see `impl IntoIterator for &Profiles`
+pub struct AvailableIter<'a>(
+    std::iter::Chain<
+        lru::Iter<'a, ed25519::PublicKey, Arc<Profile>>,
+        lru::Iter<'a, ed25519::PublicKey, Arc<Profile>>,
+    >,
+);
+
+/// the subnet of the address of a profile, see
+/// [`Profiles::reachable_fraction_by_subnet`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Subnet {
+    /// the most significant bits of an IPv4 address
+    V4(u32),
+    /// the most significant bits of the 64 bits prefix of an IPv6 address
+    V6(u64),
+}
+
+/// a profile moving between the buckets or being forgotten, see
+/// [`Profiles::on_transition`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Transition {
+    /// moved from the pool to the promoted profiles
+    Promoted,
+    /// moved from the promoted profiles back to the pool
+    Demoted,
+    /// moved to the demoted profiles
+    Quarantined,
+    /// moved from the demoted profiles back to the pool
+    QuarantineLifted,
+    /// removed on demand or by the housekeeping
+    Forgotten,
+    /// evicted to make room for another profile
+    Evicted,
+}
+
+/// called with the id of the profile and its transition, see
+/// [`Profiles::on_transition`]
+pub type TransitionHook = Box<dyn FnMut(&ed25519::PublicKey, Transition) + Send + Sync>;
+
+/// the result of [`Profiles::put_reporting`]
+#[derive(Clone, Default)]
+pub struct PutReport {
+    /// the profile was added or updated, see [`Profiles::put`]
+    pub updated: bool,
+    /// the profile evicted to make room for the new profile
+    pub evicted: Option<Arc<Profile>>,
+}
+
+/// the outcome of the gossip exchanges with a given peer, see
+/// [`Profiles::record_gossip_success`] and [`Profiles::record_gossip_failure`]
+///
+/// More fields may be added to the record, it can only be read outside of
+/// this crate (see [`Profiles::gossip_record`]).
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct GossipRecord {
+    pub last_success: Option<Time>,
+    pub last_failure: Option<Time>,
+    /// number of failures since the last success
+    pub failures: u32,
+}
+
+/// the number of lookups of the [`Profiles`], see [`Profiles::access_stats`]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct AccessStats {
+    /// the number of calls to [`Profiles::get`] that found the profile
+    pub hits: u64,
+    /// the number of calls to [`Profiles::get`] that did not find the profile
+    pub misses: u64,
+    /// the number of calls to [`Profiles::peek`]
+    pub peeks: u64,
+}
+
+/// the number of profiles in each bucket, see [`Profiles::counts`]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct BucketCounts {
+    pub dirty: usize,
+    pub pool: usize,
+    pub trusted: usize,
+}
+
+/// the signed difference between two [`BucketCounts`], see
+/// [`BucketCounts::diff`]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct CountsDelta {
+    pub dirty: i64,
+    pub pool: i64,
+    pub trusted: i64,
+}
+
+#[derive(Debug, Error)]
+pub enum CapacityError {
+    #[error("The capacity of the {bucket:?} bucket cannot be zero")]
+    Zero { bucket: Bucket },
+}
 
 pub struct Profiles {
     pub(crate) dirty: LruCache<ed25519::PublicKey, Arc<Profile>>,
     pub(crate) pool: LruCache<ed25519::PublicKey, Arc<Profile>>,
     pub(crate) trusted: LruCache<ed25519::PublicKey, Arc<Profile>>,
+
+    eviction: EvictionPolicy,
+    referenced: HashSet<ed25519::PublicKey>,
+
+    records: LruCache<ed25519::PublicKey, GossipRecord>,
+    provenance: HashMap<ed25519::PublicKey, HashSet<ed25519::PublicKey>>,
+    quarantined_since: HashMap<ed25519::PublicKey, Time>,
+    demotions: HashMap<ed25519::PublicKey, u32>,
+    max_demotions: Option<u32>,
+
+    subscribers: HashMap<Topic, HashSet<ed25519::PublicKey>>,
+
+    // atomics so the lookups through a shared reference are counted too
+    // (see `Profiles::peek`)
+    hits: AtomicU64,
+    misses: AtomicU64,
+    peeks: AtomicU64,
+
+    pressure_warning: Option<(f64, PressureWarning)>,
+    transition_hook: Option<TransitionHook>,
+
+    forgotten: Option<Vec<ed25519::PublicKey>>,
+
+    pinned: HashSet<ed25519::PublicKey>,
+}
+
+/// called with the bucket and its new pressure, see
+/// [`Profiles::set_pressure_warning`]
+pub type PressureWarning = Box<dyn FnMut(Bucket, f64) + Send + Sync>;
+
+/// put the entry in the given cache, if the cache is full the entry to
+/// remove is selected based on the eviction policy and returned
+///
+/// The pinned entries are never selected: if all the entries are pinned
+/// the new entry is rejected and returned as an error.
+fn put_with_eviction(
+    cache: &mut LruCache<ed25519::PublicKey, Arc<Profile>>,
+    eviction: EvictionPolicy,
+    referenced: &mut HashSet<ed25519::PublicKey>,
+    pinned: &HashSet<ed25519::PublicKey>,
+    id: ed25519::PublicKey,
+    profile: Arc<Profile>,
+) -> Result<Option<(ed25519::PublicKey, Arc<Profile>)>, Arc<Profile>> {
+    let mut evicted = None;
+    if cache.len() >= cache.cap() && !cache.contains(&id) {
+        // walk the unpinned entries once from the least recently used,
+        // with the second chance policy the referenced entries are
+        // skipped (and given a second chance) until an unreferenced one
+        let mut unpinned = cache
+            .iter()
+            .rev()
+            .map(|(id, _)| *id)
+            .filter(|id| !pinned.contains(id));
+        let mut second_chance = Vec::new();
+        let mut lru = unpinned.next();
+        if eviction == EvictionPolicy::SecondChance {
+            while let Some(id) = lru.filter(|id| referenced.remove(id)) {
+                second_chance.push(id);
+                lru = unpinned.next();
+            }
+        }
+
+        for id in second_chance.iter() {
+            cache.get(id);
+        }
+
+        // if all the entries were referenced we end up removing the least
+        // recently used
+        if let Some(lru) = lru.or_else(|| second_chance.first().copied()) {
+            evicted = cache.pop(&lru).map(|profile| (lru, profile));
+        } else {
+            return Err(profile);
+        }
+    }
+
+    cache.put(id, profile);
+    Ok(evicted)
 }
 
 impl Profiles {
+    /// create the profiles with the given capacity for each bucket
+    ///
+    /// A bucket with a capacity of zero silently drops every profile put
+    /// in it, use [`Profiles::try_new`] if the capacities come from an
+    /// external configuration.
     pub fn new(dirty: usize, pool: usize, trusted: usize) -> Self {
         Self {
             dirty: LruCache::new(dirty),
             pool: LruCache::new(pool),
             trusted: LruCache::new(trusted),
+
+            eviction: EvictionPolicy::Lru,
+            referenced: HashSet::new(),
+
+            records: LruCache::new(dirty + pool + trusted),
+            provenance: HashMap::new(),
+            quarantined_since: HashMap::new(),
+            demotions: HashMap::new(),
+            max_demotions: None,
+
+            subscribers: HashMap::new(),
+
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            peeks: AtomicU64::new(0),
+
+            pressure_warning: None,
+            transition_hook: None,
+
+            forgotten: None,
+
+            pinned: HashSet::new(),
+        }
+    }
+
+    /// like [`Profiles::new`] but fails if any of the capacities is zero
+    pub fn try_new(dirty: usize, pool: usize, trusted: usize) -> Result<Self, CapacityError> {
+        for (bucket, capacity) in [
+            (Bucket::Dirty, dirty),
+            (Bucket::Pool, pool),
+            (Bucket::Trusted, trusted),
+        ]
+        .iter()
+        {
+            if *capacity == 0 {
+                return Err(CapacityError::Zero { bucket: *bucket });
+            }
+        }
+
+        Ok(Self::new(dirty, pool, trusted))
+    }
+
+    pub fn eviction_policy(&self) -> EvictionPolicy {
+        self.eviction
+    }
+
+    pub fn set_eviction_policy(&mut self, eviction: EvictionPolicy) {
+        self.eviction = eviction;
+        if eviction == EvictionPolicy::Lru {
+            self.referenced.clear();
         }
     }
 
@@ -30,76 +318,2321 @@ impl Profiles {
         &self.trusted
     }
 
+    /// iterate over all the known profiles with their bucket: the promoted
+    /// profiles, then the pooled profiles, then the demoted profiles. Each
+    /// bucket is iterated from the most recently used profile.
+    ///
+    /// Iterating does not update the recency of the profiles.
+    pub fn iter(&self) -> impl Iterator<Item = (Bucket, &ed25519::PublicKey, &Arc<Profile>)> {
+        let bucket = |bucket| move |(id, profile)| (bucket, id, profile);
+
+        self.trusted
+            .iter()
+            .map(bucket(Bucket::Trusted))
+            .chain(self.pool.iter().map(bucket(Bucket::Pool)))
+            .chain(self.dirty.iter().map(bucket(Bucket::Dirty)))
+    }
+
+    /// the bucket the profile is currently in, `None` if the profile is
+    /// not known. This does not update the recency of the profile.
+    pub fn bucket(&self, id: &ed25519::PublicKey) -> Option<Bucket> {
+        if self.trusted.contains(id) {
+            Some(Bucket::Trusted)
+        } else if self.pool.contains(id) {
+            Some(Bucket::Pool)
+        } else if self.dirty.contains(id) {
+            Some(Bucket::Dirty)
+        } else {
+            None
+        }
+    }
+
+    /// how full the bucket is: its number of profiles divided by its
+    /// capacity. A bucket with no capacity is always full (`1.0`).
+    pub fn capacity_pressure(&self, bucket: Bucket) -> f64 {
+        let cache = self.cache(bucket);
+        if cache.cap() == 0 {
+            1.0
+        } else {
+            cache.len() as f64 / cache.cap() as f64
+        }
+    }
+
+    /// the maximum number of profiles of the bucket
+    pub fn capacity(&self, bucket: Bucket) -> usize {
+        self.cache(bucket).cap()
+    }
+
+    /// change the capacity of the bucket. When shrinking, the least
+    /// recently used profiles that no longer fit are forgotten (like when
+    /// they are evicted) and their ids are returned.
+    ///
+    /// The capacity may be zero, like with [`Profiles::new`]: the bucket
+    /// then drops every profile put in it.
+    pub fn resize(&mut self, bucket: Bucket, capacity: usize) -> Vec<ed25519::PublicKey> {
+        let cache = match bucket {
+            Bucket::Dirty => &mut self.dirty,
+            Bucket::Pool => &mut self.pool,
+            Bucket::Trusted => &mut self.trusted,
+        };
+
+        let mut evicted = Vec::new();
+        while cache.len() > capacity {
+            if let Some(entry) = cache.pop_lru() {
+                evicted.push(entry);
+            }
+        }
+        cache.resize(capacity);
+
+        for (id, profile) in evicted.iter() {
+            self.unindex(id, profile);
+            self.forget_metadata(id);
+            self.notify(id, Transition::Evicted);
+        }
+        let evicted: Vec<_> = evicted.into_iter().map(|(id, _)| id).collect();
+        self.records
+            .resize(self.dirty.cap() + self.pool.cap() + self.trusted.cap());
+
+        evicted
+    }
+
+    /// call `warning` every time a profile is put in a bucket and the
+    /// pressure of the bucket (see [`Profiles::capacity_pressure`]) goes
+    /// from below the `threshold` to at least the `threshold`.
+    ///
+    /// This is a chance to raise the capacity before the bucket is full
+    /// and starts evicting profiles.
+    pub fn set_pressure_warning(&mut self, threshold: f64, warning: PressureWarning) {
+        self.pressure_warning = Some((threshold, warning));
+    }
+
+    /// call `hook` every time a profile moves between the buckets or is
+    /// forgotten (removed or evicted)
+    pub fn on_transition(&mut self, hook: TransitionHook) {
+        self.transition_hook = Some(hook);
+    }
+
+    fn notify(&mut self, id: &ed25519::PublicKey, transition: Transition) {
+        if let Some(hook) = self.transition_hook.as_mut() {
+            hook(id, transition);
+        }
+    }
+
+    fn cache(&self, bucket: Bucket) -> &LruCache<ed25519::PublicKey, Arc<Profile>> {
+        match bucket {
+            Bucket::Dirty => &self.dirty,
+            Bucket::Pool => &self.pool,
+            Bucket::Trusted => &self.trusted,
+        }
+    }
+
+    /// check whether the profile has been demoted (and is therefore not
+    /// used in the layers)
+    pub fn is_quarantined(&self, id: &ed25519::PublicKey) -> bool {
+        self.bucket(id) == Some(Bucket::Dirty)
+    }
+
+    /// when the profile was demoted, `None` if the profile is not
+    /// currently demoted
+    pub fn quarantined_since(&self, id: &ed25519::PublicKey) -> Option<Time> {
+        self.quarantined_since.get(id).copied()
+    }
+
+    /// the demoted profiles that have been demoted for at least `max_age`.
+    /// The demoted profiles without a recorded demotion time are included.
+    pub fn expired_quarantine(&self, max_age: Duration, now: Time) -> Vec<ed25519::PublicKey> {
+        self.expired_quarantine_iter(max_age, now).collect()
+    }
+
+    /// like [`Profiles::expired_quarantine`] but without collecting the ids
+    pub fn expired_quarantine_iter(
+        &self,
+        max_age: Duration,
+        now: Time,
+    ) -> impl Iterator<Item = ed25519::PublicKey> + '_ {
+        self.dirty
+            .iter()
+            .map(|(id, _)| id)
+            .filter(move |id| {
+                if let Some(since) = self.quarantined_since.get(*id) {
+                    let elapsed = u32::from(now).saturating_sub(u32::from(*since));
+                    elapsed as u64 >= max_age.as_secs()
+                } else {
+                    true
+                }
+            })
+            .copied()
+    }
+
+    /// the number of times the profile was demoted (moved from the pool to
+    /// the demoted profiles), see [`Profiles::set_max_demotions`]
+    pub fn demotion_count(&self, id: &ed25519::PublicKey) -> u32 {
+        self.demotions.get(id).copied().unwrap_or(0)
+    }
+
+    /// forget the profiles that have already been demoted `max` times
+    /// instead of demoting them again, so the peers that keep failing
+    /// are not kept around forever. `None` (the default) never forgets
+    /// them.
+    pub fn set_max_demotions(&mut self, max: Option<u32>) {
+        self.max_demotions = max;
+    }
+
+    /// the ratio of the known profiles that are demoted, `0.0` if there
+    /// are no known profiles
+    pub fn quarantine_ratio(&self) -> f64 {
+        let all = self.dirty.len() + self.pool.len() + self.trusted.len();
+        if all == 0 {
+            0.0
+        } else {
+            self.dirty.len() as f64 / all as f64
+        }
+    }
+
+    /// if there are no available profiles left (all the known profiles are
+    /// demoted) move the demoted pinned profiles (see [`Profiles::pin`])
+    /// back to the pool, so we have someone to gossip with.
+    ///
+    /// This is a last resort measure: it is better to retry connecting to
+    /// the bootstrap peers than to stay isolated. At most the capacity of
+    /// the pool is recovered, starting from the most recently used, the
+    /// other profiles stay demoted. Returns the ids of the profiles moved
+    /// back to the pool, from the most recently used.
+    pub fn recover_from_isolation(&mut self) -> Vec<ed25519::PublicKey> {
+        if !self.trusted.is_empty() || !self.pool.is_empty() {
+            return Vec::new();
+        }
+
+        let recovered: Vec<_> = self
+            .dirty
+            .iter()
+            .map(|(id, _)| *id)
+            .filter(|id| self.pinned.contains(id))
+            .take(self.pool.cap())
+            .collect();
+
+        // from the least recently used so the pool keeps the same order
+        for id in recovered.iter().rev() {
+            if let Some(profile) = self.dirty.pop(id) {
+                self.put_in(Bucket::Pool, *id, profile);
+                self.notify(id, Transition::QuarantineLifted);
+            }
+        }
+
+        recovered
+    }
+
+    /// move the profile from the pool to the trusted bucket, or from the
+    /// dirty bucket to the pool. If the capacity of the trusted bucket is
+    /// zero the profiles of the pool stay in the pool.
     pub fn promote(&mut self, entry: &ed25519::PublicKey) {
-        if let Some(profile) = self.pool.pop(entry) {
+        if self.trusted.cap() == 0 {
+            // nowhere to promote the profile to
+        } else if let Some(profile) = self.pool.pop(entry) {
             // if there is an overflow coming up, instead of losing
             // the entries we would rotate from the trusted LRU
             // we demote the least used to the lower pool
             while self.trusted.len() >= self.trusted.cap() {
                 if let Some((id, profile)) = self.trusted.pop_lru() {
-                    self.pool.put(id, profile);
+                    self.put_in(Bucket::Pool, id, profile);
+                    self.notify(&id, Transition::Demoted);
                 } else {
                     unreachable!("cap should be greater than 0")
                 }
             }
 
-            self.trusted.put(*entry, profile);
+            self.put_in(Bucket::Trusted, *entry, profile);
+            self.notify(entry, Transition::Promoted);
         }
 
         if let Some(profile) = self.dirty.pop(entry) {
-            self.pool.put(*entry, profile);
+            self.put_in(Bucket::Pool, *entry, profile);
+            self.notify(entry, Transition::QuarantineLifted);
         }
     }
 
     pub fn demote(&mut self, entry: &ed25519::PublicKey) {
         if let Some(profile) = self.pool.pop(entry) {
-            self.dirty.put(*entry, profile);
+            self.put_in_quarantine(entry, profile);
         } else if let Some(profile) = self.trusted.pop(entry) {
-            self.pool.put(*entry, profile);
+            self.put_in(Bucket::Pool, *entry, profile);
+            self.notify(entry, Transition::Demoted);
+        }
+    }
+
+    /// demote the profile directly, whether it is promoted or pooled.
+    /// Returns `false` if the profile is not known, or if it was forgotten
+    /// instead (see [`Profiles::set_max_demotions`]).
+    pub fn quarantine(&mut self, id: &ed25519::PublicKey) -> bool {
+        if let Some(profile) = self.trusted.pop(id).or_else(|| self.pool.pop(id)) {
+            self.put_in_quarantine(id, profile);
+        }
+
+        self.bucket(id).is_some()
+    }
+
+    /// put the profile, already taken out of its bucket, in the dirty
+    /// bucket. It is forgotten instead if it has already been demoted
+    /// `max_demotions` times (see [`Profiles::set_max_demotions`]).
+    fn put_in_quarantine(&mut self, id: &ed25519::PublicKey, profile: Arc<Profile>) {
+        let demotions = self.demotions.entry(*id).or_insert(0);
+        if matches!(self.max_demotions, Some(max) if *demotions >= max) {
+            self.unindex(id, &profile);
+            self.remove(id);
+            self.notify(id, Transition::Forgotten);
+            return;
         }
+        *demotions += 1;
+
+        self.put_in(Bucket::Dirty, *id, profile);
+        self.notify(id, Transition::Quarantined);
+    }
+
+    /// move the demoted profile back to the pool. Returns `false` if the
+    /// profile is not known.
+    pub fn lift_quarantine(&mut self, id: &ed25519::PublicKey) -> bool {
+        if let Some(profile) = self.dirty.pop(id) {
+            self.put_in(Bucket::Pool, *id, profile);
+            self.notify(id, Transition::QuarantineLifted);
+        }
+
+        self.bucket(id).is_some()
+    }
+
+    /// remove all the demoted profiles (see [`Profiles::remove`]) and
+    /// return them, from the most recently used. The available profiles are
+    /// left untouched.
+    pub fn drain_quarantined(&mut self) -> Vec<Arc<Profile>> {
+        let ids: Vec<_> = self.dirty.iter().map(|(id, _)| *id).collect();
+
+        ids.iter().filter_map(|id| self.remove(id)).collect()
+    }
+
+    /// mark the profile as recently used so it is kept longer in its
+    /// bucket (for example after a successful exchange with the peer).
+    /// Returns `false` if the profile is not known.
+    ///
+    /// Unlike [`Profiles::get`] the lookup is not counted in the
+    /// [`Profiles::access_stats`].
+    pub fn touch(&mut self, id: &ed25519::PublicKey) -> bool {
+        self.bump(id).is_some()
+    }
+
+    /// mark all the given profiles as recently used, see
+    /// [`Profiles::touch`]. The unknown ids are ignored.
+    pub fn touch_batch(&mut self, ids: &[ed25519::PublicKey]) {
+        for id in ids {
+            self.touch(id);
+        }
+    }
+
+    /// record the given peer successfully responded to a gossip exchange
+    ///
+    /// the peer is promoted (see [`Profiles::promote`]) and marked as
+    /// recently used so it is kept longer than the peers we are not
+    /// interacting with.
+    pub fn record_gossip_success(&mut self, id: &ed25519::PublicKey) {
+        if let Some(record) = self.record_mut(id) {
+            record.last_success = Some(Time::now());
+            record.failures = 0;
+        }
+
+        self.promote(id);
+        let _ = self.bump(id);
+    }
+
+    /// record the given peer failed to respond to a gossip exchange
+    ///
+    /// if `demote` is true the peer is also demoted (see [`Profiles::demote`])
+    pub fn record_gossip_failure(&mut self, id: &ed25519::PublicKey, demote: bool) {
+        if let Some(record) = self.record_mut(id) {
+            record.last_failure = Some(Time::now());
+            record.failures = record.failures.saturating_add(1);
+        }
+
+        if demote {
+            self.demote(id);
+        }
+    }
+
+    /// the available profiles (promoted or pooled) we did not fail to
+    /// gossip with (see [`Profiles::record_gossip_failure`]) within the
+    /// last `cooldown`. The profiles we never failed to gossip with are
+    /// included.
+    pub fn dialable(&self, cooldown: Duration, now: Time) -> Vec<ed25519::PublicKey> {
+        self.dialable_iter(cooldown, now).collect()
+    }
+
+    /// like [`Profiles::dialable`] but without collecting the ids
+    pub fn dialable_iter(
+        &self,
+        cooldown: Duration,
+        now: Time,
+    ) -> impl Iterator<Item = ed25519::PublicKey> + '_ {
+        self.trusted
+            .iter()
+            .chain(self.pool.iter())
+            .map(|(id, _)| id)
+            .filter(move |id| {
+                let last_failure = self
+                    .records
+                    .peek(*id)
+                    .and_then(|record| record.last_failure);
+
+                if let Some(last_failure) = last_failure {
+                    let elapsed = u32::from(now).saturating_sub(u32::from(last_failure));
+                    elapsed as u64 >= cooldown.as_secs()
+                } else {
+                    true
+                }
+            })
+            .copied()
+    }
+
+    /// the outcome of the gossip exchanges with the profile, `None` if we
+    /// never recorded any for this profile. See also
+    /// [`Profiles::demotion_count`].
+    pub fn gossip_record(&self, id: &ed25519::PublicKey) -> Option<&GossipRecord> {
+        self.records.peek(id)
+    }
+
+    fn record_mut(&mut self, id: &ed25519::PublicKey) -> Option<&mut GossipRecord> {
+        self.bucket(id)?;
+
+        if !self.records.contains(id) {
+            self.records.put(*id, GossipRecord::default());
+        }
+        self.records.get_mut(id)
+    }
+
+    /// put the profile in the given bucket, moving it out of the bucket it
+    /// is already known in (if any). The gossip record and the other
+    /// metadata of the profile are kept.
+    pub(crate) fn move_to_bucket(&mut self, profile: Arc<Profile>, bucket: Bucket) {
+        let id = profile.id();
+        let previous = [
+            self.dirty.pop(&id),
+            self.pool.pop(&id),
+            self.trusted.pop(&id),
+        ];
+        let known = previous.iter().any(Option::is_some);
+        for previous in previous.iter().flatten() {
+            self.unindex(&id, previous);
+        }
+
+        if known {
+            self.put_in(bucket, id, profile);
+        } else {
+            let _ = self.try_put_in(bucket, id, profile);
+        }
+    }
+
+    /// put the profile, taken out of its bucket, in the given bucket.
+    /// Returns the profile evicted to make room for it (if any).
+    ///
+    /// If the bucket has no capacity or is full of pinned profiles the
+    /// profile is dropped and forgotten like an evicted profile (see
+    /// [`Profiles::pin`]).
+    fn put_in(
+        &mut self,
+        bucket: Bucket,
+        id: ed25519::PublicKey,
+        profile: Arc<Profile>,
+    ) -> Option<Arc<Profile>> {
+        match self.try_put_in(bucket, id, profile) {
+            Ok(evicted) => evicted,
+            Err(profile) => {
+                self.unindex(&id, &profile);
+                self.forget_metadata(&id);
+                self.notify(&id, Transition::Evicted);
+                None
+            }
+        }
+    }
+
+    /// like [`Profiles::put_in`] but the rejected profile is returned as
+    /// an error, for the profiles that were in none of the buckets
+    fn try_put_in(
+        &mut self,
+        bucket: Bucket,
+        id: ed25519::PublicKey,
+        profile: Arc<Profile>,
+    ) -> Result<Option<Arc<Profile>>, Arc<Profile>> {
+        let pressure = self.capacity_pressure(bucket);
+
+        let cache = match bucket {
+            Bucket::Dirty => &mut self.dirty,
+            Bucket::Pool => &mut self.pool,
+            Bucket::Trusted => &mut self.trusted,
+        };
+
+        let index = Arc::clone(&profile);
+        let evicted = put_with_eviction(
+            cache,
+            self.eviction,
+            &mut self.referenced,
+            &self.pinned,
+            id,
+            profile,
+        )?;
+        self.index(&id, &index);
+
+        if let Some((evicted, profile)) = evicted.as_ref() {
+            self.unindex(evicted, profile);
+            self.forget_metadata(evicted);
+            self.notify(evicted, Transition::Evicted);
+        }
+
+        if bucket == Bucket::Dirty {
+            self.quarantined_since.entry(id).or_insert_with(Time::now);
+        } else {
+            self.quarantined_since.remove(&id);
+        }
+
+        let new_pressure = self.capacity_pressure(bucket);
+        if let Some((threshold, warning)) = self.pressure_warning.as_mut() {
+            if pressure < *threshold && new_pressure >= *threshold {
+                warning(bucket, new_pressure);
+            }
+        }
+
+        Ok(evicted.map(|(_, profile)| profile))
+    }
+
+    /// remove the profile from all the buckets and forget everything we
+    /// know about it (gossip record, sources...). Returns the removed
+    /// profile.
+    pub fn remove(&mut self, id: &ed25519::PublicKey) -> Option<Arc<Profile>> {
+        let trusted = self.trusted.pop(id);
+        let pool = self.pool.pop(id);
+        let dirty = self.dirty.pop(id);
+        self.forget_metadata(id);
+
+        let removed = trusted.or(pool).or(dirty);
+        if let Some(profile) = removed.as_ref() {
+            self.unindex(id, profile);
+            self.notify(id, Transition::Forgotten);
+        }
+        removed
+    }
+
+    /// never evict the profile to make room for another one, even if it is
+    /// the least recently used of its bucket. The id does not need to be
+    /// known yet, it stays pinned after the profile is removed.
+    ///
+    /// A profile put in a bucket full of pinned profiles is rejected (see
+    /// [`PutReport::updated`]). Pinning does not prevent the profile from
+    /// being demoted, removed or dropped when its bucket is resized.
+    pub fn pin(&mut self, id: ed25519::PublicKey) {
+        self.pinned.insert(id);
+    }
+
+    /// allow evicting the profile again, see [`Profiles::pin`]
+    pub fn unpin(&mut self, id: &ed25519::PublicKey) {
+        self.pinned.remove(id);
+    }
+
+    pub fn is_pinned(&self, id: &ed25519::PublicKey) -> bool {
+        self.pinned.contains(id)
+    }
+
+    /// forget all the profiles (see [`Profiles::remove`]), the capacities
+    /// and the eviction policy are kept
+    pub fn clear(&mut self) {
+        let ids: Vec<_> = self.iter().map(|(_, id, _)| *id).collect();
+        for id in ids.iter() {
+            self.remove(id);
+        }
+    }
+
+    /// no profile is known, regardless of the bucket
+    pub fn is_empty(&self) -> bool {
+        self.trusted.is_empty() && self.pool.is_empty() && self.dirty.is_empty()
+    }
+
+    /// the known profiles (including the demoted ones) subscribed to the
+    /// given topic, `None` if no known profile is subscribed to it
+    ///
+    /// The index is maintained as the profiles are inserted, updated and
+    /// removed so this is `O(1)`.
+    pub fn subscribers(&self, topic: &Topic) -> Option<&HashSet<ed25519::PublicKey>> {
+        self.subscribers.get(topic)
+    }
+
+    fn index(&mut self, id: &ed25519::PublicKey, profile: &Profile) {
+        for topic in profile.topics() {
+            self.subscribers.entry(*topic).or_default().insert(*id);
+        }
+    }
+
+    fn unindex(&mut self, id: &ed25519::PublicKey, profile: &Profile) {
+        for topic in profile.topics() {
+            if let Some(subscribers) = self.subscribers.get_mut(topic) {
+                subscribers.remove(id);
+                if subscribers.is_empty() {
+                    self.subscribers.remove(topic);
+                }
+            }
+        }
+    }
+
+    /// replace the profile in the given bucket, keeping the topic index
+    /// in sync with the new subscriptions
+    fn update_in(&mut self, bucket: Bucket, id: ed25519::PublicKey, profile: Arc<Profile>) {
+        let cache = match bucket {
+            Bucket::Dirty => &mut self.dirty,
+            Bucket::Pool => &mut self.pool,
+            Bucket::Trusted => &mut self.trusted,
+        };
+
+        if let Some(previous) = cache.put(id, Arc::clone(&profile)) {
+            self.unindex(&id, &previous);
+        }
+        self.index(&id, &profile);
+    }
+
+    /// remove the profiles for which the predicate returns `false`, see
+    /// [`Profiles::remove`]. Returns the ids of the removed profiles.
+    ///
+    /// The order of the remaining profiles is unchanged.
+    pub fn retain<F>(&mut self, mut predicate: F) -> Vec<ed25519::PublicKey>
+    where
+        F: FnMut(Bucket, &ed25519::PublicKey, &Arc<Profile>) -> bool,
+    {
+        let removed: Vec<_> = self
+            .iter()
+            .filter(|(bucket, id, profile)| !predicate(*bucket, id, profile))
+            .map(|(_, id, _)| *id)
+            .collect();
+
+        for id in removed.iter() {
+            self.remove(id);
+        }
+
+        removed
+    }
+
+    /// forget everything we know about the profile outside of the buckets
+    fn forget_metadata(&mut self, id: &ed25519::PublicKey) {
+        self.referenced.remove(id);
+        self.records.pop(id);
+        self.provenance.remove(id);
+        self.quarantined_since.remove(id);
+        self.demotions.remove(id);
+
+        if let Some(forgotten) = self.forgotten.as_mut() {
+            forgotten.push(*id);
+        }
+    }
+
+    /// start keeping the ids of the profiles that are forgotten (removed or
+    /// evicted) until they are collected with [`Profiles::take_forgotten`]
+    pub(crate) fn track_forgotten(&mut self) {
+        self.forgotten.get_or_insert_with(Vec::new);
+    }
+
+    /// the ids of the profiles forgotten since the last call, see
+    /// [`Profiles::track_forgotten`]
+    pub(crate) fn take_forgotten(&mut self) -> Vec<ed25519::PublicKey> {
+        self.forgotten
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
     }
 
     pub fn put(&mut self, id: ed25519::PublicKey, profile: Arc<Profile>) -> bool {
+        self.put_reporting(id, profile).updated
+    }
+
+    /// like [`Profiles::put`] but also report the profile that was evicted
+    /// from the pool to make room for the new profile
+    ///
+    /// A profile inserted or updated this way has no source (see
+    /// [`Profiles::put_from`]), it is never forgotten by
+    /// [`Profiles::forget_all_from_source`].
+    pub fn put_reporting(&mut self, id: ed25519::PublicKey, profile: Arc<Profile>) -> PutReport {
+        let report = self.put_in_buckets(id, Arc::clone(&profile));
+
+        if self
+            .lookup(&id)
+            .map_or(false, |stored| Arc::ptr_eq(stored, &profile))
+        {
+            self.provenance.remove(&id);
+        }
+
+        report
+    }
+
+    fn put_in_buckets(&mut self, id: ed25519::PublicKey, profile: Arc<Profile>) -> PutReport {
+        let mut report = PutReport::default();
+
         if let Some(entry) = self.dirty.peek(&id).cloned() {
-            if entry.last_update() < profile.last_update() {
-                self.dirty.put(id, profile);
+            if profile.is_newer_than(&entry) {
+                self.update_in(Bucket::Dirty, id, profile);
             }
-            false
         } else if let Some(entry) = self.trusted.peek(&id).cloned() {
-            if entry.last_update() < profile.last_update() {
-                self.trusted.put(id, profile);
-                true
-            } else {
-                false
+            if profile.is_newer_than(&entry) {
+                self.update_in(Bucket::Trusted, id, profile);
+                report.updated = true;
             }
         } else if let Some(entry) = self.pool.peek(&id).cloned() {
-            if entry.last_update() < profile.last_update() {
-                self.pool.put(id, profile);
-                true
-            } else {
-                false
+            if profile.is_newer_than(&entry) {
+                self.update_in(Bucket::Pool, id, profile);
+                report.updated = true;
             }
         } else {
-            self.pool.put(id, profile);
-            true
+            // a rejected profile was never in the buckets, there is nothing
+            // to forget about it
+            report.evicted = self.try_put_in(Bucket::Pool, id, profile).unwrap_or(None);
+            report.updated = self.pool.contains(&id);
         }
+
+        report
     }
 
-    pub fn get(&mut self, id: &ed25519::PublicKey) -> Option<&Arc<Profile>> {
-        if let Some(profile) = self.trusted.get(id) {
-            Some(profile)
-        } else if let Some(profile) = self.pool.get(id) {
-            Some(profile)
-        } else if let Some(profile) = self.dirty.get(id) {
-            Some(profile)
-        } else {
-            None
+    /// run the periodic housekeeping of the profiles, see [`GcOptions`]
+    ///
+    /// the age of a profile is computed from the time of its last update
+    /// (see [`Profile::last_update`]). The promoted profiles are never
+    /// forgotten. The quarantines are lifted last, so the profiles moved
+    /// back to the pool are not forgotten as stale in the same pass.
+    pub fn gc(&mut self, now: Time, options: GcOptions) -> GcReport {
+        fn older_than(
+            cache: &LruCache<ed25519::PublicKey, Arc<Profile>>,
+            now: Time,
+            max_age: Option<Duration>,
+        ) -> Vec<ed25519::PublicKey> {
+            let max_age = if let Some(max_age) = max_age {
+                max_age.as_secs()
+            } else {
+                return Vec::new();
+            };
+
+            cache
+                .iter()
+                .filter(|(_, profile)| {
+                    let age = u32::from(now).saturating_sub(u32::from(profile.last_update()));
+                    age as u64 >= max_age
+                })
+                .map(|(id, _)| *id)
+                .collect()
         }
-    }
-}
 
-impl Default for Profiles {
-    fn default() -> Self {
-        Self::new(512, 256, 128)
+        let forgotten_dirty = older_than(&self.dirty, now, options.forget_dirty_after);
+        for id in forgotten_dirty.iter() {
+            self.remove(id);
+        }
+
+        let forgotten_stale = older_than(&self.pool, now, options.forget_stale_after);
+        for id in forgotten_stale.iter() {
+            self.remove(id);
+        }
+
+        let quarantine_lifted = if let Some(max_age) = options.lift_quarantine_after {
+            self.expired_quarantine(max_age, now)
+        } else {
+            Vec::new()
+        };
+        for id in quarantine_lifted.iter() {
+            self.lift_quarantine(id);
+        }
+
+        GcReport {
+            forgotten_dirty,
+            forgotten_stale,
+            quarantine_lifted,
+        }
+    }
+
+    /// the gossips of all the available profiles (the promoted and the
+    /// pooled ones, not the demoted ones).
+    ///
+    /// This is `O(n)` and clones the gossip of every available profile, see
+    /// [`Profiles::available_gossips_iter`].
+    pub fn available_gossips(&self) -> Vec<Gossip> {
+        self.available_gossips_iter().cloned().collect()
+    }
+
+    /// like [`Profiles::available_gossips`] but without cloning the gossips
+    pub fn available_gossips_iter(&self) -> impl Iterator<Item = &Gossip> {
+        self.into_iter().map(|profile| profile.gossip())
+    }
+
+    /// count the known profiles (regardless of their bucket) per age
+    ///
+    /// The age of a profile is the time elapsed since its last update (see
+    /// [`Profile::last_update`]). The `buckets` are the boundaries of the
+    /// histogram, in increasing order: the `i`th entry of the returned
+    /// histogram counts the profiles younger than `buckets[i]` (and not
+    /// younger than `buckets[i - 1]`); the last entry counts the profiles
+    /// older than all the boundaries. The returned histogram has therefore
+    /// `buckets.len() + 1` entries.
+    pub fn age_distribution(&self, now: Time, buckets: &[Duration]) -> Vec<usize> {
+        let mut histogram = vec![0; buckets.len() + 1];
+
+        for (_, profile) in self
+            .trusted
+            .iter()
+            .chain(self.pool.iter())
+            .chain(self.dirty.iter())
+        {
+            let age = u32::from(now).saturating_sub(u32::from(profile.last_update()));
+            let age = Duration::from_secs(age as u64);
+
+            let index = buckets
+                .iter()
+                .position(|boundary| age < *boundary)
+                .unwrap_or(buckets.len());
+            histogram[index] += 1;
+        }
+
+        histogram
+    }
+
+    /// count the known profiles (regardless of their bucket) per prefix
+    /// of their id.
+    ///
+    /// The prefix is made of the `prefix_bits` most significant bits of
+    /// the id (at most 64). A heavily skewed histogram is a sign that
+    /// someone is generating ids close to a given target in order to
+    /// dominate a region of the rings.
+    pub fn prefix_histogram(&self, prefix_bits: u32) -> BTreeMap<u64, usize> {
+        let prefix_bits = prefix_bits.min(u64::BITS);
+        let mut histogram = BTreeMap::new();
+
+        for (id, _) in self
+            .trusted
+            .iter()
+            .chain(self.pool.iter())
+            .chain(self.dirty.iter())
+        {
+            let bytes: [u8; 8] = id.as_ref()[..8]
+                .try_into()
+                .expect("the public key is at least 8 bytes long");
+            let prefix = u64::from_be_bytes(bytes)
+                .checked_shr(u64::BITS - prefix_bits)
+                .unwrap_or(0);
+
+            *histogram.entry(prefix).or_insert(0) += 1;
+        }
+
+        histogram
+    }
+
+    /// the fraction of the available profiles (the promoted and pooled
+    /// ones) per subnet of their address.
+    ///
+    /// The subnet is made of the `prefix_bits` most significant bits of
+    /// the IP address: of its 32 bits for an IPv4 address and of its 64
+    /// most significant bits for an IPv6 address. The IPv4 and IPv6
+    /// subnets are kept apart. A subnet holding a large fraction of the
+    /// available profiles is a sign that someone is trying to eclipse us.
+    pub fn reachable_fraction_by_subnet(&self, prefix_bits: u32) -> BTreeMap<Subnet, f64> {
+        let mut counts = BTreeMap::new();
+        let mut all = 0;
+
+        for profile in self {
+            let subnet = match profile.address().ip() {
+                IpAddr::V4(ip) => Subnet::V4(
+                    u32::from(ip)
+                        .checked_shr(u32::BITS - prefix_bits.min(u32::BITS))
+                        .unwrap_or(0),
+                ),
+                IpAddr::V6(ip) => Subnet::V6(
+                    ((u128::from(ip) >> 64) as u64)
+                        .checked_shr(u64::BITS - prefix_bits.min(u64::BITS))
+                        .unwrap_or(0),
+                ),
+            };
+
+            *counts.entry(subnet).or_insert(0usize) += 1;
+            all += 1;
+        }
+
+        counts
+            .into_iter()
+            .map(|(subnet, count)| (subnet, count as f64 / all as f64))
+            .collect()
+    }
+
+    /// the first available profile (promoted then pooled, see
+    /// `impl IntoIterator for &Profiles`) matching the predicate
+    ///
+    /// This does not update the recency of the profiles.
+    pub fn find<F>(&self, predicate: F) -> Option<&Arc<Profile>>
+    where
+        F: Fn(&Profile) -> bool,
+    {
+        self.into_iter().find(|profile| predicate(profile))
+    }
+
+    /// all the available profiles matching the predicate, see
+    /// [`Profiles::find`]
+    pub fn find_all<F>(&self, predicate: F) -> Vec<&Arc<Profile>>
+    where
+        F: Fn(&Profile) -> bool,
+    {
+        self.find_iter(predicate).collect()
+    }
+
+    /// like [`Profiles::find_all`] but without collecting the profiles
+    pub fn find_iter<F>(&self, predicate: F) -> impl Iterator<Item = &Arc<Profile>>
+    where
+        F: Fn(&Profile) -> bool,
+    {
+        self.into_iter().filter(move |profile| predicate(profile))
+    }
+
+    /// like [`Profiles::put`] but record the profile was gossiped by `source`
+    ///
+    /// The source is recorded if the profile is added, updated or if it is
+    /// the version we already know: a profile may then have multiple
+    /// sources. It is not recorded if the gossiped profile is older than
+    /// the one we know or if it was rejected. A profile we already knew
+    /// without a source (see [`Profiles::put`]) stays without a source.
+    /// See [`Profiles::forget_all_from_source`].
+    pub fn put_from(
+        &mut self,
+        source: ed25519::PublicKey,
+        id: ed25519::PublicKey,
+        profile: Arc<Profile>,
+    ) -> bool {
+        let known = self.bucket(&id).is_some();
+        let updated = self.put_in_buckets(id, Arc::clone(&profile)).updated;
+
+        let accepted = self
+            .lookup(&id)
+            .map_or(false, |stored| !stored.is_newer_than(&profile));
+        if accepted && (!known || self.provenance.contains_key(&id)) {
+            self.provenance.entry(id).or_default().insert(source);
+        }
+
+        updated
+    }
+
+    /// forget all the profiles that have only been gossiped by `source`
+    ///
+    /// The profiles that have also been gossiped by other sources are kept
+    /// (but `source` is removed from their sources). The profiles without a
+    /// source (see [`Profiles::put`]) are kept too.
+    ///
+    /// Returns the ids of the forgotten profiles.
+    pub fn forget_all_from_source(
+        &mut self,
+        source: &ed25519::PublicKey,
+    ) -> Vec<ed25519::PublicKey> {
+        let mut forgotten = Vec::new();
+
+        for (id, sources) in self.provenance.iter_mut() {
+            if sources.remove(source) && sources.is_empty() {
+                forgotten.push(*id);
+            }
+        }
+
+        for id in forgotten.iter() {
+            self.remove(id);
+        }
+
+        forgotten
+    }
+
+    pub fn get(&mut self, id: &ed25519::PublicKey) -> Option<&Arc<Profile>> {
+        if self.bucket(id).is_some() {
+            *self.hits.get_mut() += 1;
+        } else {
+            *self.misses.get_mut() += 1;
+        }
+
+        self.bump(id)
+    }
+
+    /// get the profile without updating its recency
+    pub fn peek(&self, id: &ed25519::PublicKey) -> Option<&Arc<Profile>> {
+        self.peeks.fetch_add(1, atomic::Ordering::Relaxed);
+
+        self.lookup(id)
+    }
+
+    /// like [`Profiles::peek`] but without counting the lookup, for the
+    /// lookups done on behalf of the other functions of the crate
+    pub(crate) fn lookup(&self, id: &ed25519::PublicKey) -> Option<&Arc<Profile>> {
+        self.trusted
+            .peek(id)
+            .or_else(|| self.pool.peek(id))
+            .or_else(|| self.dirty.peek(id))
+    }
+
+    /// [`Profiles::peek`] all the given ids at once, the result has one
+    /// entry per id (`None` for the unknown ids)
+    pub fn peek_many(&self, ids: &[ed25519::PublicKey]) -> Vec<Option<&Arc<Profile>>> {
+        ids.iter().map(|id| self.peek(id)).collect()
+    }
+
+    /// the number of lookups since the `Profiles` were created
+    pub fn access_stats(&self) -> AccessStats {
+        AccessStats {
+            hits: self.hits.load(atomic::Ordering::Relaxed),
+            misses: self.misses.load(atomic::Ordering::Relaxed),
+            peeks: self.peeks.load(atomic::Ordering::Relaxed),
+        }
+    }
+
+    /// the number of profiles in each bucket
+    pub fn counts(&self) -> BucketCounts {
+        BucketCounts {
+            dirty: self.dirty.len(),
+            pool: self.pool.len(),
+            trusted: self.trusted.len(),
+        }
+    }
+
+    /// mark the profile as recently used without counting it in the
+    /// access stats
+    fn bump(&mut self, id: &ed25519::PublicKey) -> Option<&Arc<Profile>> {
+        if self.eviction == EvictionPolicy::SecondChance && self.bucket(id).is_some() {
+            self.referenced.insert(*id);
+        }
+
+        if let Some(profile) = self.trusted.get(id) {
+            Some(profile)
+        } else if let Some(profile) = self.pool.get(id) {
+            Some(profile)
+        } else if let Some(profile) = self.dirty.get(id) {
+            Some(profile)
+        } else {
+            None
+        }
+    }
+}
+
+/// the lookups between two snapshots of the [`AccessStats`], the counters
+/// never go below zero
+impl std::ops::Sub for AccessStats {
+    type Output = Self;
+
+    fn sub(self, previous: Self) -> Self {
+        Self {
+            hits: self.hits.saturating_sub(previous.hits),
+            misses: self.misses.saturating_sub(previous.misses),
+            peeks: self.peeks.saturating_sub(previous.peeks),
+        }
+    }
+}
+
+impl BucketCounts {
+    /// the change of the counts since the `previous` snapshot, negative
+    /// where a bucket shrank
+    pub fn diff(&self, previous: &Self) -> CountsDelta {
+        let delta = |now: usize, before: usize| now as i64 - before as i64;
+
+        CountsDelta {
+            dirty: delta(self.dirty, previous.dirty),
+            pool: delta(self.pool, previous.pool),
+            trusted: delta(self.trusted, previous.trusted),
+        }
+    }
+}
+
+/// same as [`BucketCounts::diff`]
+impl std::ops::Sub for &BucketCounts {
+    type Output = CountsDelta;
+
+    fn sub(self, previous: Self) -> CountsDelta {
+        self.diff(previous)
+    }
+}
+
+/// iterate over the available profiles only: the promoted profiles then
+/// the pooled profiles. The demoted profiles are not yielded.
+///
+/// Iterating does not update the recency of the profiles.
+///
+/// ```
+/// # use poldercast::Profiles;
+/// let profiles = Profiles::default();
+///
+/// for profile in &profiles {
+///     println!("{}", profile.address());
+/// }
+/// ```
+impl<'a> IntoIterator for &'a Profiles {
+    type Item = &'a Arc<Profile>;
+    type IntoIter = AvailableIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        AvailableIter(self.trusted.iter().chain(self.pool.iter()))
+    }
+}
+
+impl<'a> Iterator for AvailableIter<'a> {
+    type Item = &'a Arc<Profile>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(_, profile)| profile)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl Default for Profiles {
+    fn default() -> Self {
+        Self::new(512, 256, 128)
+    }
+}
+
+/// put the profiles (see [`Profiles::put`]), once the pool is full the
+/// least recently used profiles are evicted
+impl Extend<Arc<Profile>> for Profiles {
+    fn extend<I: IntoIterator<Item = Arc<Profile>>>(&mut self, profiles: I) {
+        for profile in profiles {
+            self.put(profile.id(), profile);
+        }
+    }
+}
+
+/// put the profiles in a [`Profiles::default`], see `impl Extend for
+/// Profiles` to use other capacities
+impl std::iter::FromIterator<Arc<Profile>> for Profiles {
+    fn from_iter<I: IntoIterator<Item = Arc<Profile>>>(profiles: I) -> Self {
+        let mut collection = Self::default();
+        collection.extend(profiles);
+        collection
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl Profiles {
+    /// insert the profile directly in the given bucket, bypassing the
+    /// usual promotion and demotion rules.
+    ///
+    /// This allows putting the `Profiles` in arbitrary states (including
+    /// invalid ones, like having the same profile in multiple buckets)
+    /// in order to check the invariants are kept by the other operations.
+    pub fn test_insert_in_bucket(&mut self, profile: Arc<Profile>, bucket: Bucket) {
+        let _ = self.try_put_in(bucket, profile.id(), profile);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{InterestLevel, Subscription, Subscriptions};
+    use keynesis::Seed;
+    use quickcheck::{Arbitrary, Gen};
+
+    impl Arbitrary for Bucket {
+        fn arbitrary(g: &mut Gen) -> Self {
+            *g.choose(&[Bucket::Dirty, Bucket::Pool, Bucket::Trusted])
+                .unwrap()
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    enum Operation {
+        Put(usize),
+        Promote(usize),
+        Demote(usize),
+    }
+
+    impl Arbitrary for Operation {
+        fn arbitrary(g: &mut Gen) -> Self {
+            let index = usize::arbitrary(g);
+            match u8::arbitrary(g) % 3 {
+                0 => Operation::Put(index),
+                1 => Operation::Promote(index),
+                _ => Operation::Demote(index),
+            }
+        }
+    }
+
+    fn profile(seed: u8) -> Arc<Profile> {
+        let mut rng = Seed::from([seed; Seed::SIZE]).into_rand_chacha();
+        let id = ed25519::SecretKey::new(&mut rng);
+        let address = "127.0.0.1:9000".parse().unwrap();
+        let gossip = Gossip::new(address, &id, Subscriptions::new().as_slice());
+        Arc::new(Profile::from_gossip(gossip))
+    }
+
+    fn buckets_of(profiles: &Profiles, id: &ed25519::PublicKey) -> usize {
+        profiles.dirty.contains(id) as usize
+            + profiles.pool.contains(id) as usize
+            + profiles.trusted.contains(id) as usize
+    }
+
+    #[test]
+    fn second_chance_keeps_referenced_profile() {
+        let (a, b, c) = (profile(1), profile(2), profile(3));
+
+        let mut lru = Profiles::new(2, 2, 2);
+        let mut second_chance = Profiles::new(2, 2, 2);
+        second_chance.set_eviction_policy(EvictionPolicy::SecondChance);
+
+        for profiles in [&mut lru, &mut second_chance] {
+            profiles.put(a.id(), Arc::clone(&a));
+            assert!(profiles.get(&a.id()).is_some());
+            profiles.put(b.id(), Arc::clone(&b));
+            profiles.put(c.id(), Arc::clone(&c));
+        }
+
+        assert!(!lru.pool().contains(&a.id()));
+        assert!(lru.pool().contains(&b.id()));
+
+        assert!(second_chance.pool().contains(&a.id()));
+        assert!(!second_chance.pool().contains(&b.id()));
+        assert!(second_chance.pool().contains(&c.id()));
+    }
+
+    #[test]
+    fn second_chance_all_referenced() {
+        let mut profiles = Profiles::new(3, 3, 3);
+        profiles.set_eviction_policy(EvictionPolicy::SecondChance);
+        for seed in 1..=3 {
+            profiles.put(profile(seed).id(), profile(seed));
+            assert!(profiles.get(&profile(seed).id()).is_some());
+        }
+        profiles.pin(profile(1).id());
+
+        // the pinned profile is skipped, the referenced ones all get their
+        // second chance so the least recently used one is evicted
+        profiles.put(profile(4).id(), profile(4));
+        assert!(profiles.pool().contains(&profile(1).id()));
+        assert!(!profiles.pool().contains(&profile(2).id()));
+
+        // the second chance was used, the profile is not referenced anymore
+        profiles.put(profile(5).id(), profile(5));
+        assert!(!profiles.pool().contains(&profile(3).id()));
+        assert!(profiles.pool().contains(&profile(4).id()));
+    }
+
+    #[test]
+    fn gossip_success_survives_eviction() {
+        let (responsive, untouched) = (profile(1), profile(2));
+
+        let mut profiles = Profiles::new(2, 2, 2);
+        profiles.put(responsive.id(), Arc::clone(&responsive));
+        profiles.put(untouched.id(), Arc::clone(&untouched));
+
+        profiles.record_gossip_success(&responsive.id());
+
+        for seed in 3..8 {
+            let profile = profile(seed);
+            profiles.put(profile.id(), profile);
+        }
+
+        assert!(profiles.get(&responsive.id()).is_some());
+        assert!(profiles.get(&untouched.id()).is_none());
+
+        let record = profiles.gossip_record(&responsive.id()).unwrap();
+        assert!(record.last_success.is_some());
+        assert_eq!(record.failures, 0);
+
+        profiles.record_gossip_failure(&responsive.id(), false);
+        profiles.record_gossip_failure(&responsive.id(), true);
+        let record = profiles.gossip_record(&responsive.id()).unwrap();
+        assert!(record.last_failure.is_some());
+        assert_eq!(record.failures, 2);
+        assert!(profiles.pool().contains(&responsive.id()));
+    }
+
+    #[test]
+    fn available_gossips_only_contains_available_ids() {
+        let (dirty, pool, trusted) = (profile(1), profile(2), profile(3));
+
+        let mut profiles = Profiles::default();
+        profiles.test_insert_in_bucket(Arc::clone(&dirty), Bucket::Dirty);
+        profiles.test_insert_in_bucket(Arc::clone(&pool), Bucket::Pool);
+        profiles.test_insert_in_bucket(Arc::clone(&trusted), Bucket::Trusted);
+
+        let ids: HashSet<ed25519::PublicKey> = profiles
+            .available_gossips()
+            .iter()
+            .map(|gossip| gossip.id())
+            .collect();
+
+        let expected: HashSet<ed25519::PublicKey> =
+            vec![pool.id(), trusted.id()].into_iter().collect();
+        assert_eq!(ids, expected);
+    }
+
+    #[test]
+    fn forget_all_from_source() {
+        let (source, other) = (profile(1).id(), profile(2).id());
+        let (exclusive, shared, unsourced) = (profile(3), profile(4), profile(5));
+
+        let mut profiles = Profiles::default();
+        profiles.put_from(source, exclusive.id(), Arc::clone(&exclusive));
+        profiles.put_from(source, shared.id(), Arc::clone(&shared));
+        profiles.put_from(other, shared.id(), Arc::clone(&shared));
+        profiles.put(unsourced.id(), Arc::clone(&unsourced));
+
+        assert_eq!(
+            profiles.forget_all_from_source(&source),
+            vec![exclusive.id()]
+        );
+        assert!(profiles.get(&exclusive.id()).is_none());
+        assert!(profiles.get(&shared.id()).is_some());
+        assert!(profiles.get(&unsourced.id()).is_some());
+
+        assert_eq!(profiles.forget_all_from_source(&other), vec![shared.id()]);
+        assert!(profiles.get(&shared.id()).is_none());
+    }
+
+    #[test]
+    fn forget_all_from_source_keeps_unsourced_profiles() {
+        let (bad, good) = (profile(1).id(), profile(2).id());
+        let mut rng = Seed::from([3; Seed::SIZE]).into_rand_chacha();
+        let key = ed25519::SecretKey::new(&mut rng);
+        let now = u32::from(Time::now());
+        let at = |time: u32| {
+            let gossip = Gossip::new_at(
+                "127.0.0.1:9000".parse().unwrap(),
+                &key,
+                Subscriptions::new().as_slice(),
+                Time::from(time),
+            );
+            Arc::new(Profile::from_gossip(gossip))
+        };
+        let id = key.public_key();
+
+        // learned on our own, then re-gossiped (newer) by the bad source
+        let mut profiles = Profiles::default();
+        profiles.put(id, at(now - 10));
+        assert!(profiles.put_from(bad, id, at(now)));
+        assert!(profiles.forget_all_from_source(&bad).is_empty());
+        assert!(profiles.peek(&id).is_some());
+
+        // a stale gossip does not add the bad source
+        let mut profiles = Profiles::default();
+        profiles.put_from(good, id, at(now));
+        assert!(!profiles.put_from(bad, id, at(now - 10)));
+        assert!(profiles.forget_all_from_source(&bad).is_empty());
+        assert_eq!(profiles.forget_all_from_source(&good), vec![id]);
+
+        // learning the profile on our own drops its sources
+        let mut profiles = Profiles::default();
+        profiles.put_from(bad, id, at(now - 10));
+        profiles.put(id, at(now));
+        assert!(profiles.forget_all_from_source(&bad).is_empty());
+        assert!(profiles.peek(&id).is_some());
+    }
+
+    #[test]
+    fn into_iter_skips_dirty_profiles() {
+        let (dirty, pool, trusted) = (profile(1), profile(2), profile(3));
+
+        let mut profiles = Profiles::default();
+        profiles.test_insert_in_bucket(Arc::clone(&dirty), Bucket::Dirty);
+        profiles.test_insert_in_bucket(Arc::clone(&pool), Bucket::Pool);
+        profiles.test_insert_in_bucket(Arc::clone(&trusted), Bucket::Trusted);
+
+        let mut ids = Vec::new();
+        for profile in &profiles {
+            ids.push(profile.id());
+        }
+
+        assert_eq!(ids, vec![trusted.id(), pool.id()]);
+    }
+
+    #[test]
+    fn touch() {
+        let (touched, other, unknown) = (profile(1), profile(2), profile(3));
+
+        let mut profiles = Profiles::new(2, 2, 2);
+        profiles.put(touched.id(), Arc::clone(&touched));
+        profiles.put(other.id(), Arc::clone(&other));
+
+        assert!(profiles.touch(&touched.id()));
+        assert!(!profiles.touch(&unknown.id()));
+        assert_eq!(profiles.access_stats(), AccessStats::default());
+
+        profiles.put(unknown.id(), unknown);
+        assert!(profiles.pool().contains(&touched.id()));
+        assert!(!profiles.pool().contains(&other.id()));
+    }
+
+    #[test]
+    fn touch_batch_survives_eviction() {
+        let touched = [profile(1), profile(2)];
+        let untouched = [profile(3), profile(4)];
+
+        let mut profiles = Profiles::new(4, 4, 4);
+        for profile in touched.iter().chain(untouched.iter()) {
+            profiles.put(profile.id(), Arc::clone(profile));
+        }
+
+        let ids: Vec<_> = touched.iter().map(|profile| profile.id()).collect();
+        profiles.touch_batch(&ids);
+
+        for seed in 5..7 {
+            let profile = profile(seed);
+            profiles.put(profile.id(), profile);
+        }
+
+        assert!(touched
+            .iter()
+            .all(|profile| profiles.pool().contains(&profile.id())));
+        assert!(untouched
+            .iter()
+            .all(|profile| !profiles.pool().contains(&profile.id())));
+    }
+
+    #[test]
+    fn bucket_follows_promotion_and_demotion() {
+        let profile = profile(1);
+        let id = profile.id();
+
+        let mut profiles = Profiles::default();
+        assert_eq!(profiles.bucket(&id), None);
+
+        profiles.put(id, profile);
+        assert_eq!(profiles.bucket(&id), Some(Bucket::Pool));
+        assert!(!profiles.is_quarantined(&id));
+
+        profiles.demote(&id);
+        assert_eq!(profiles.bucket(&id), Some(Bucket::Dirty));
+        assert!(profiles.is_quarantined(&id));
+
+        profiles.promote(&id);
+        profiles.promote(&id);
+        assert_eq!(profiles.bucket(&id), Some(Bucket::Trusted));
+        assert!(!profiles.is_quarantined(&id));
+    }
+
+    #[test]
+    fn dialable_excludes_recent_failures() {
+        let (recent, old, never) = (profile(1), profile(2), profile(3));
+
+        let mut profiles = Profiles::default();
+        for profile in [&recent, &old, &never] {
+            profiles.put(profile.id(), Arc::clone(profile));
+        }
+
+        profiles.record_gossip_failure(&recent.id(), false);
+        profiles.record_gossip_failure(&old.id(), false);
+
+        let now = Time::now();
+        let long_ago = Time::from(u32::from(now) - 3600);
+        profiles.records.get_mut(&old.id()).unwrap().last_failure = Some(long_ago);
+
+        let dialable: HashSet<_> = profiles
+            .dialable(Duration::from_secs(60), now)
+            .into_iter()
+            .collect();
+        let expected: HashSet<_> = vec![old.id(), never.id()].into_iter().collect();
+        assert_eq!(dialable, expected);
+    }
+
+    #[test]
+    fn age_distribution_histogram() {
+        let now = Time::now();
+
+        let mut profiles = Profiles::default();
+        for (seed, age) in [(1, 10), (2, 30), (3, 59), (4, 60), (5, 3000), (6, 4000)] {
+            let mut rng = Seed::from([seed; Seed::SIZE]).into_rand_chacha();
+            let id = ed25519::SecretKey::new(&mut rng);
+            let gossip = Gossip::new_at(
+                "127.0.0.1:9000".parse().unwrap(),
+                &id,
+                Subscriptions::new().as_slice(),
+                Time::from(u32::from(now) - age),
+            );
+            let profile = Arc::new(Profile::from_gossip(gossip));
+            profiles.put(profile.id(), profile);
+        }
+
+        let buckets = [
+            Duration::from_secs(60),
+            Duration::from_secs(3600),
+            Duration::from_secs(86400),
+        ];
+        assert_eq!(profiles.age_distribution(now, &buckets), vec![3, 2, 1, 0]);
+        assert_eq!(profiles.age_distribution(now, &[]), vec![6]);
+    }
+
+    #[test]
+    fn quarantine_ratio() {
+        let mut profiles = Profiles::default();
+        assert_eq!(profiles.quarantine_ratio(), 0.0);
+
+        for seed in 1..=4 {
+            let profile = profile(seed);
+            profiles.put(profile.id(), profile);
+        }
+        profiles.demote(&profile(1).id());
+
+        assert_eq!(profiles.quarantine_ratio(), 0.25);
+    }
+
+    #[test]
+    fn reachable_fraction_by_subnet() {
+        let peer = |seed: u8, address: &str| {
+            let mut rng = Seed::from([seed; Seed::SIZE]).into_rand_chacha();
+            let id = ed25519::SecretKey::new(&mut rng);
+            let gossip = Gossip::new(
+                address.parse().unwrap(),
+                &id,
+                Subscriptions::new().as_slice(),
+            );
+            Arc::new(Profile::from_gossip(gossip))
+        };
+
+        let mut profiles = Profiles::default();
+        for seed in 1..=4 {
+            let profile = peer(seed, &format!("10.0.0.{}:9000", seed));
+            profiles.put(profile.id(), profile);
+        }
+        let profile = peer(5, "192.168.1.1:9000");
+        profiles.put(profile.id(), profile);
+        let demoted = peer(6, "192.168.1.2:9000");
+        profiles.put(demoted.id(), Arc::clone(&demoted));
+        profiles.demote(&demoted.id());
+
+        let fractions = profiles.reachable_fraction_by_subnet(24);
+        assert_eq!(fractions.len(), 2);
+        assert_eq!(fractions[&Subnet::V4(0x0a_00_00)], 0.8);
+        assert_eq!(fractions[&Subnet::V4(0xc0_a8_01)], 0.2);
+
+        let fractions = profiles.reachable_fraction_by_subnet(0);
+        assert_eq!(
+            fractions.into_iter().collect::<Vec<_>>(),
+            vec![(Subnet::V4(0), 1.0)]
+        );
+
+        // the same bits in both families are different subnets
+        let v6 = peer(7, "[a00::1]:9000");
+        profiles.put(v6.id(), v6);
+        let fractions = profiles.reachable_fraction_by_subnet(8);
+        assert_eq!(fractions.len(), 3);
+        assert_eq!(fractions[&Subnet::V4(0x0a)], 4.0 / 6.0);
+        assert_eq!(fractions[&Subnet::V4(0xc0)], 1.0 / 6.0);
+        assert_eq!(fractions[&Subnet::V6(0x0a)], 1.0 / 6.0);
+    }
+
+    #[test]
+    fn remove() {
+        let (trusted, pooled) = (profile(1), profile(2));
+        let source = profile(3).id();
+
+        let mut profiles = Profiles::default();
+        profiles.put_from(source, trusted.id(), Arc::clone(&trusted));
+        profiles.put(pooled.id(), Arc::clone(&pooled));
+        profiles.promote(&trusted.id());
+        profiles.record_gossip_failure(&trusted.id(), false);
+
+        let removed = profiles.remove(&trusted.id()).unwrap();
+        assert_eq!(removed.id(), trusted.id());
+        assert_eq!(profiles.bucket(&trusted.id()), None);
+        assert_eq!(profiles.gossip_record(&trusted.id()), None);
+        assert!(profiles.forget_all_from_source(&source).is_empty());
+
+        assert!(profiles.remove(&trusted.id()).is_none());
+        assert_eq!(profiles.bucket(&pooled.id()), Some(Bucket::Pool));
+    }
+
+    #[test]
+    fn retain() {
+        let mut profiles = Profiles::default();
+        for seed in 1..=6 {
+            let profile = profile(seed);
+            profiles.put(profile.id(), profile);
+        }
+        let id = |seed| profile(seed).id();
+        profiles.promote(&id(1));
+        profiles.demote(&id(2));
+
+        let banned = [id(2), id(4)];
+        let removed = profiles.retain(|_, id, _| !banned.contains(id));
+        assert_eq!(removed, vec![id(4), id(2)]);
+
+        let remaining: Vec<_> = profiles.iter().map(|(_, id, _)| *id).collect();
+        assert_eq!(remaining, vec![id(1), id(6), id(5), id(3)]);
+
+        let removed = profiles.retain(|bucket, _, _| bucket == Bucket::Trusted);
+        assert_eq!(removed, vec![id(6), id(5), id(3)]);
+        assert_eq!(profiles.bucket(&id(1)), Some(Bucket::Trusted));
+    }
+
+    #[test]
+    fn resize() {
+        let mut profiles = Profiles::new(4, 4, 4);
+        for seed in 1..=4 {
+            let profile = profile(seed);
+            let id = profile.id();
+            profiles.put(id, profile);
+            profiles.record_gossip_success(&id);
+        }
+        let id = |seed| profile(seed).id();
+        // `record_gossip_success` promoted all of them
+        assert_eq!(profiles.trusted().len(), 4);
+
+        assert_eq!(profiles.resize(Bucket::Trusted, 6), Vec::new());
+        assert_eq!(profiles.capacity(Bucket::Trusted), 6);
+
+        let evicted = profiles.resize(Bucket::Trusted, 1);
+        assert_eq!(evicted, vec![id(1), id(2), id(3)]);
+        assert_eq!(profiles.capacity(Bucket::Trusted), 1);
+        assert_eq!(
+            profiles.trusted().peek_lru().map(|(id, _)| *id),
+            Some(id(4))
+        );
+        for id in evicted.iter() {
+            assert_eq!(profiles.bucket(id), None);
+            assert_eq!(profiles.gossip_record(id), None);
+        }
+        assert!(profiles.gossip_record(&id(4)).is_some());
+
+        assert_eq!(profiles.resize(Bucket::Trusted, 0), vec![id(4)]);
+        let other = profile(5);
+        profiles.put(other.id(), Arc::clone(&other));
+        profiles.promote(&other.id());
+        assert_eq!(profiles.bucket(&other.id()), Some(Bucket::Pool));
+        assert!(profiles.trusted().is_empty());
+    }
+
+    #[test]
+    fn quarantined_since() {
+        let (demoted, evicted) = (profile(1), profile(2));
+
+        let mut profiles = Profiles::new(1, 4, 4);
+        profiles.put(demoted.id(), Arc::clone(&demoted));
+        profiles.put(evicted.id(), Arc::clone(&evicted));
+        assert_eq!(profiles.quarantined_since(&demoted.id()), None);
+
+        let before = Time::now();
+        profiles.demote(&demoted.id());
+        let since = profiles.quarantined_since(&demoted.id()).unwrap();
+        assert!(since >= before && since <= Time::now());
+
+        profiles.promote(&demoted.id());
+        assert_eq!(profiles.bucket(&demoted.id()), Some(Bucket::Pool));
+        assert_eq!(profiles.quarantined_since(&demoted.id()), None);
+
+        profiles.demote(&demoted.id());
+        profiles.demote(&evicted.id());
+        assert_eq!(profiles.bucket(&demoted.id()), None);
+        assert_eq!(profiles.quarantined_since(&demoted.id()), None);
+        assert!(profiles.quarantined_since(&evicted.id()).is_some());
+    }
+
+    #[test]
+    fn iter() {
+        let mut profiles = Profiles::default();
+        for seed in 1..=4 {
+            let profile = profile(seed);
+            profiles.put(profile.id(), profile);
+        }
+        let id = |seed| profile(seed).id();
+        profiles.promote(&id(1));
+        profiles.demote(&id(2));
+
+        let all: Vec<_> = profiles
+            .iter()
+            .map(|(bucket, id, _)| (bucket, *id))
+            .collect();
+        assert_eq!(
+            all,
+            vec![
+                (Bucket::Trusted, id(1)),
+                (Bucket::Pool, id(4)),
+                (Bucket::Pool, id(3)),
+                (Bucket::Dirty, id(2)),
+            ]
+        );
+
+        // iterating did not change the least recently used profile
+        assert_eq!(profiles.pool().peek_lru().map(|(id, _)| *id), Some(id(3)));
+    }
+
+    #[test]
+    fn find() {
+        let topic = Topic::new([1; Topic::SIZE]);
+        let subscribed = |profile: &Profile| {
+            profile
+                .subscriptions()
+                .iter()
+                .any(|subscription| subscription.topic() == topic)
+        };
+
+        let mut profiles = Profiles::default();
+        for seed in 1..5 {
+            let profile = profile(seed);
+            profiles.put(profile.id(), profile);
+        }
+        assert!(profiles.find(subscribed).is_none());
+        assert!(profiles.find_all(subscribed).is_empty());
+
+        let mut rng = Seed::from([5; Seed::SIZE]).into_rand_chacha();
+        let key = ed25519::SecretKey::new(&mut rng);
+        let with_topic = Profile::builder()
+            .id(&key)
+            .address("127.0.0.1:9005".parse().unwrap())
+            .subscribe(topic, InterestLevel::new(1))
+            .build()
+            .unwrap();
+        let id = with_topic.id();
+        profiles.put(id, Arc::new(with_topic));
+
+        assert_eq!(
+            profiles.find(subscribed).map(|profile| profile.id()),
+            Some(id)
+        );
+        assert_eq!(profiles.find_all(subscribed).len(), 1);
+
+        profiles.demote(&id);
+        assert!(profiles.find(subscribed).is_none());
+    }
+
+    #[test]
+    fn pressure_warning() {
+        use std::sync::Mutex;
+
+        let warnings = Arc::new(Mutex::new(Vec::new()));
+        let mut profiles = Profiles::new(4, 4, 4);
+        profiles.set_pressure_warning(0.75, {
+            let warnings = Arc::clone(&warnings);
+            Box::new(move |bucket, pressure| warnings.lock().unwrap().push((bucket, pressure)))
+        });
+
+        for seed in 1..=2 {
+            let profile = profile(seed);
+            profiles.put(profile.id(), profile);
+        }
+        assert_eq!(profiles.capacity_pressure(Bucket::Pool), 0.5);
+        assert!(warnings.lock().unwrap().is_empty());
+
+        for seed in 3..=6 {
+            let profile = profile(seed);
+            profiles.put(profile.id(), profile);
+        }
+        assert_eq!(profiles.capacity_pressure(Bucket::Pool), 1.0);
+        assert_eq!(*warnings.lock().unwrap(), vec![(Bucket::Pool, 0.75)]);
+    }
+
+    #[test]
+    fn on_transition() {
+        use std::sync::Mutex;
+
+        let transitions = Arc::new(Mutex::new(Vec::new()));
+        let mut profiles = Profiles::new(4, 1, 1);
+        profiles.on_transition({
+            let transitions = Arc::clone(&transitions);
+            Box::new(move |id, transition| transitions.lock().unwrap().push((*id, transition)))
+        });
+
+        let (a, b, c) = (profile(1).id(), profile(2).id(), profile(3).id());
+        profiles.put(a, profile(1));
+        profiles.promote(&a);
+        profiles.put(b, profile(2));
+        profiles.promote(&b);
+        profiles.demote(&a);
+        profiles.lift_quarantine(&a);
+        profiles.put(c, profile(3));
+        profiles.remove(&b);
+
+        assert_eq!(
+            *transitions.lock().unwrap(),
+            vec![
+                (a, Transition::Promoted),
+                (a, Transition::Demoted),
+                (b, Transition::Promoted),
+                (a, Transition::Quarantined),
+                (a, Transition::QuarantineLifted),
+                (a, Transition::Evicted),
+                (b, Transition::Forgotten),
+            ]
+        );
+    }
+
+    #[test]
+    fn on_transition_rejected() {
+        use std::sync::Mutex;
+
+        let transitions = Arc::new(Mutex::new(Vec::new()));
+        let mut profiles = Profiles::new(4, 0, 1);
+        profiles.on_transition({
+            let transitions = Arc::clone(&transitions);
+            Box::new(move |id, transition| transitions.lock().unwrap().push((*id, transition)))
+        });
+
+        // never inserted, nothing to report
+        let a = profile(1).id();
+        assert!(!profiles.put(a, profile(1)));
+        assert!(transitions.lock().unwrap().is_empty());
+
+        // taken out of the dirty bucket and dropped, reported as evicted
+        profiles.test_insert_in_bucket(profile(1), Bucket::Dirty);
+        profiles.lift_quarantine(&a);
+        assert_eq!(profiles.bucket(&a), None);
+        assert_eq!(transitions.lock().unwrap()[0], (a, Transition::Evicted));
+    }
+
+    #[test]
+    fn try_new_zero_capacity() {
+        assert!(Profiles::try_new(1, 1, 1).is_ok());
+        assert!(matches!(
+            Profiles::try_new(1, 0, 1),
+            Err(CapacityError::Zero {
+                bucket: Bucket::Pool
+            })
+        ));
+        assert!(matches!(
+            Profiles::try_new(0, 1, 0),
+            Err(CapacityError::Zero {
+                bucket: Bucket::Dirty
+            })
+        ));
+    }
+
+    #[test]
+    fn promote_zero_trusted_capacity() {
+        let (pool, dirty) = (profile(1), profile(2));
+
+        let mut profiles = Profiles::new(4, 4, 0);
+        profiles.put(pool.id(), Arc::clone(&pool));
+        profiles.put(dirty.id(), Arc::clone(&dirty));
+        profiles.demote(&dirty.id());
+
+        profiles.promote(&pool.id());
+        assert_eq!(profiles.bucket(&pool.id()), Some(Bucket::Pool));
+        profiles.promote(&dirty.id());
+        assert_eq!(profiles.bucket(&dirty.id()), Some(Bucket::Pool));
+        assert!(profiles.trusted().is_empty());
+    }
+
+    #[test]
+    fn peek_many() {
+        let (known, other, unknown) = (profile(1), profile(2), profile(3));
+
+        let mut profiles = Profiles::default();
+        profiles.put(known.id(), Arc::clone(&known));
+        profiles.put(other.id(), Arc::clone(&other));
+
+        let peeked: Vec<_> = profiles
+            .peek_many(&[unknown.id(), known.id(), known.id()])
+            .into_iter()
+            .map(|profile| profile.map(|profile| profile.id()))
+            .collect();
+        assert_eq!(peeked, vec![None, Some(known.id()), Some(known.id())]);
+
+        // peeking did not make `known` the most recently used
+        assert_eq!(
+            profiles.pool().peek_lru().map(|(id, _)| *id),
+            Some(known.id())
+        );
+        assert_eq!(profiles.access_stats().peeks, 3);
+    }
+
+    #[test]
+    fn put_reporting_eviction() {
+        let mut profiles = Profiles::new(4, 2, 4);
+        for seed in 1..=2 {
+            let profile = profile(seed);
+            let report = profiles.put_reporting(profile.id(), profile);
+            assert!(report.updated);
+            assert!(report.evicted.is_none());
+        }
+
+        let evicted = profile(1);
+        let newest = profile(3);
+        let report = profiles.put_reporting(newest.id(), Arc::clone(&newest));
+        assert!(report.updated);
+        assert_eq!(
+            report.evicted.map(|profile| profile.id()),
+            Some(evicted.id())
+        );
+
+        let report = profiles.put_reporting(newest.id(), newest);
+        assert!(!report.updated);
+        assert!(report.evicted.is_none());
+    }
+
+    #[test]
+    fn max_demotions() {
+        let offender = profile(1);
+        let id = offender.id();
+
+        let mut profiles = Profiles::default();
+        profiles.set_max_demotions(Some(2));
+        profiles.put(id, offender);
+
+        for count in 1..=2 {
+            profiles.demote(&id);
+            assert_eq!(profiles.bucket(&id), Some(Bucket::Dirty));
+            assert_eq!(profiles.demotion_count(&id), count);
+            profiles.promote(&id);
+            assert_eq!(profiles.bucket(&id), Some(Bucket::Pool));
+        }
+
+        profiles.demote(&id);
+        assert_eq!(profiles.bucket(&id), None);
+        assert_eq!(profiles.demotion_count(&id), 0);
+
+        // quarantining counts as a demotion too
+        let offender = profile(2);
+        let id = offender.id();
+        profiles.put(id, offender);
+        profiles.promote(&id);
+        assert!(profiles.quarantine(&id));
+        assert_eq!(profiles.demotion_count(&id), 1);
+        profiles.lift_quarantine(&id);
+        profiles.demote(&id);
+        assert_eq!(profiles.demotion_count(&id), 2);
+        profiles.lift_quarantine(&id);
+        assert!(!profiles.quarantine(&id));
+        assert_eq!(profiles.bucket(&id), None);
+    }
+
+    #[test]
+    fn put_keeps_the_newest_profile() {
+        let mut rng = Seed::from([1; Seed::SIZE]).into_rand_chacha();
+        let key = ed25519::SecretKey::new(&mut rng);
+        let now = u32::from(Time::now());
+        let at = |port: u16, time: u32| {
+            let address = format!("127.0.0.1:{}", port).parse().unwrap();
+            let gossip = Gossip::new_at(
+                address,
+                &key,
+                Subscriptions::new().as_slice(),
+                Time::from(time),
+            );
+            Arc::new(Profile::from_gossip(gossip))
+        };
+        let (fresh, stale, same) = (at(9000, now), at(9001, now - 10), at(9002, now));
+        let id = fresh.id();
+        assert!(fresh.is_newer_than(&stale));
+        assert!(!stale.is_newer_than(&fresh));
+        assert!(!same.is_newer_than(&fresh));
+
+        let mut profiles = Profiles::default();
+        assert!(profiles.put(id, Arc::clone(&fresh)));
+        assert!(!profiles.put(id, stale));
+        assert!(!profiles.put(id, same));
+        assert_eq!(profiles.peek(&id).unwrap().address(), fresh.address());
+
+        assert!(profiles.put(id, at(9003, now + 1)));
+        assert_eq!(profiles.peek(&id).unwrap().address().port(), 9003);
+    }
+
+    #[test]
+    fn recover_from_isolation() {
+        let mut profiles = Profiles::new(10, 2, 2);
+        for seed in 1..=5 {
+            let profile = profile(seed);
+            profiles.pin(profile.id());
+            let id = profile.id();
+            assert!(profiles.put(id, profile));
+            profiles.demote(&id);
+        }
+        let unpinned = profile(6);
+        profiles.put(unpinned.id(), Arc::clone(&unpinned));
+        assert!(profiles.recover_from_isolation().is_empty());
+        profiles.demote(&unpinned.id());
+        assert_eq!(profiles.dirty().len(), 6);
+
+        let recovered = profiles.recover_from_isolation();
+        assert_eq!(recovered, vec![profile(5).id(), profile(4).id()]);
+        assert_eq!(
+            profiles
+                .pool()
+                .iter()
+                .map(|(id, _)| *id)
+                .collect::<Vec<_>>(),
+            recovered
+        );
+        assert_eq!(profiles.dirty().len(), 4);
+        assert_eq!(profiles.bucket(&unpinned.id()), Some(Bucket::Dirty));
+        assert!(profiles.recover_from_isolation().is_empty());
+    }
+
+    #[test]
+    fn pin() {
+        let (bootstrap, other) = (profile(1), profile(2));
+
+        let mut profiles = Profiles::new(2, 2, 2);
+        profiles.pin(bootstrap.id());
+        profiles.put(bootstrap.id(), Arc::clone(&bootstrap));
+
+        for seed in 3..20 {
+            let profile = profile(seed);
+            assert!(profiles.put(profile.id(), profile));
+        }
+        assert_eq!(profiles.bucket(&bootstrap.id()), Some(Bucket::Pool));
+        assert_eq!(profiles.pool().len(), 2);
+
+        let churn = profiles.pool().peek_lru().map(|(id, _)| *id).unwrap();
+        assert_eq!(churn, bootstrap.id());
+        profiles.pin(profile(19).id());
+        assert!(!profiles.put(other.id(), Arc::clone(&other)));
+        assert_eq!(profiles.bucket(&other.id()), None);
+        assert_eq!(profiles.bucket(&bootstrap.id()), Some(Bucket::Pool));
+
+        profiles.unpin(&bootstrap.id());
+        assert!(!profiles.is_pinned(&bootstrap.id()));
+        assert!(profiles.put(other.id(), Arc::clone(&other)));
+        assert_eq!(profiles.bucket(&bootstrap.id()), None);
+    }
+
+    #[test]
+    fn from_iter() {
+        let profiles: Profiles = (1..4).map(profile).collect();
+        assert_eq!(profiles.pool().len(), 3);
+        assert_eq!(
+            profiles.capacity(Bucket::Pool),
+            Profiles::default().capacity(Bucket::Pool)
+        );
+
+        let mut profiles = Profiles::new(1, 2, 1);
+        profiles.extend((1..4).map(profile));
+        let pooled: Vec<_> = profiles.pool().iter().map(|(id, _)| *id).collect();
+        assert_eq!(pooled, vec![profile(3).id(), profile(2).id()]);
+    }
+
+    #[test]
+    fn clear() {
+        let mut profiles = Profiles::new(2, 3, 4);
+        assert!(profiles.is_empty());
+
+        for seed in 1..5 {
+            let profile = profile(seed);
+            profiles.put(profile.id(), profile);
+        }
+        let (promoted, demoted) = (profile(1).id(), profile(2).id());
+        profiles.promote(&promoted);
+        profiles.demote(&demoted);
+        profiles.record_gossip_success(&promoted);
+        assert!(!profiles.is_empty());
+
+        profiles.clear();
+        assert!(profiles.is_empty());
+        assert_eq!(profiles.iter().count(), 0);
+        assert!(profiles.gossip_record(&promoted).is_none());
+        assert_eq!(profiles.demotion_count(&demoted), 0);
+        assert_eq!(profiles.capacity(Bucket::Dirty), 2);
+        assert_eq!(profiles.capacity(Bucket::Pool), 3);
+        assert_eq!(profiles.capacity(Bucket::Trusted), 4);
+    }
+
+    #[test]
+    fn subscribers() {
+        let mut rng = Seed::from([1; Seed::SIZE]).into_rand_chacha();
+        let key = ed25519::SecretKey::new(&mut rng);
+        let address = "127.0.0.1:9000".parse().unwrap();
+        let (a, b) = (Topic::new([1; Topic::SIZE]), Topic::new([2; Topic::SIZE]));
+        let now = u32::from(Time::now());
+
+        let subscribed = |topics: &[Topic], time: u32| {
+            let mut subscriptions = Subscriptions::new();
+            for topic in topics {
+                let subscription = Subscription::new(*topic, InterestLevel::new(1));
+                subscriptions.push(subscription.as_slice()).unwrap();
+            }
+            let gossip = Gossip::new_at(address, &key, subscriptions.as_slice(), Time::from(time));
+            Arc::new(Profile::from_gossip(gossip))
+        };
+
+        let mut profiles = Profiles::default();
+        let other = profile(2);
+        profiles.put(other.id(), Arc::clone(&other));
+        assert!(profiles.subscribers(&a).is_none());
+
+        let first = subscribed(&[a], now);
+        let id = first.id();
+        profiles.put(id, first);
+        assert!(profiles.subscribers(&a).unwrap().contains(&id));
+        assert!(profiles.subscribers(&b).is_none());
+
+        profiles.promote(&id);
+        profiles.put(id, subscribed(&[a, b], now + 1));
+        assert_eq!(profiles.subscribers(&a).unwrap().len(), 1);
+        assert!(profiles.subscribers(&b).unwrap().contains(&id));
+
+        profiles.demote(&id);
+        profiles.demote(&id);
+        profiles.put(id, subscribed(&[b], now + 2));
+        assert!(profiles.subscribers(&a).is_none());
+        assert!(profiles.subscribers(&b).unwrap().contains(&id));
+
+        profiles.lift_quarantine(&id);
+        profiles.set_max_demotions(Some(1));
+        profiles.demote(&id);
+        assert_eq!(profiles.bucket(&id), None);
+        assert!(profiles.subscribers(&b).is_none());
+    }
+
+    #[test]
+    fn expired_quarantine() {
+        let (recent, old, untracked) = (profile(1), profile(2), profile(3));
+        let max_age = Duration::from_secs(60);
+
+        let mut profiles = Profiles::default();
+        for profile in &[&recent, &old, &untracked] {
+            profiles.put(profile.id(), Arc::clone(profile));
+            profiles.demote(&profile.id());
+        }
+        let now = Time::now();
+        profiles
+            .quarantined_since
+            .insert(old.id(), Time::from(u32::from(now) - 120));
+        profiles.quarantined_since.remove(&untracked.id());
+
+        let mut expired = profiles.expired_quarantine(max_age, now);
+        expired.sort();
+        let mut expected = vec![old.id(), untracked.id()];
+        expected.sort();
+        assert_eq!(expired, expected);
+
+        let later = Time::from(u32::from(now) + 60);
+        assert_eq!(profiles.expired_quarantine(max_age, later).len(), 3);
+    }
+
+    #[test]
+    fn drain_quarantined() {
+        let mut profiles = Profiles::default();
+        for seed in 1..6 {
+            let profile = profile(seed);
+            profiles.put(profile.id(), profile);
+        }
+        let demoted = [profile(1).id(), profile(2).id()];
+        for id in demoted.iter() {
+            profiles.demote(id);
+        }
+        let count = profiles.dirty().len();
+
+        let drained = profiles.drain_quarantined();
+        assert_eq!(drained.len(), count);
+        assert_eq!(drained[0].id(), demoted[1]);
+        assert_eq!(drained[1].id(), demoted[0]);
+        assert!(profiles.dirty().is_empty());
+        assert_eq!(profiles.pool().len(), 3);
+        assert!(profiles.drain_quarantined().is_empty());
+    }
+
+    #[test]
+    fn quarantine() {
+        let (trusted, unknown) = (profile(1), profile(2));
+        let id = trusted.id();
+
+        let mut profiles = Profiles::default();
+        profiles.put(id, trusted);
+        profiles.promote(&id);
+
+        assert!(profiles.quarantine(&id));
+        assert_eq!(profiles.bucket(&id), Some(Bucket::Dirty));
+        assert!(profiles.quarantine(&id));
+        assert_eq!(profiles.bucket(&id), Some(Bucket::Dirty));
+
+        assert!(profiles.lift_quarantine(&id));
+        assert_eq!(profiles.bucket(&id), Some(Bucket::Pool));
+        assert!(profiles.lift_quarantine(&id));
+        assert_eq!(profiles.bucket(&id), Some(Bucket::Pool));
+
+        assert!(!profiles.quarantine(&unknown.id()));
+        assert!(!profiles.lift_quarantine(&unknown.id()));
+        assert_eq!(profiles.bucket(&unknown.id()), None);
+    }
+
+    #[test]
+    fn access_stats() {
+        let (known, unknown) = (profile(1), profile(2));
+
+        let mut profiles = Profiles::default();
+        profiles.put(known.id(), Arc::clone(&known));
+
+        assert!(profiles.get(&known.id()).is_some());
+        assert!(profiles.get(&known.id()).is_some());
+        assert!(profiles.get(&unknown.id()).is_none());
+        assert!(profiles.peek(&known.id()).is_some());
+        profiles.touch_batch(&[known.id()]);
+
+        assert_eq!(
+            profiles.access_stats(),
+            AccessStats {
+                hits: 2,
+                misses: 1,
+                peeks: 1,
+            }
+        );
+
+        let previous = profiles.access_stats();
+        assert!(profiles.get(&known.id()).is_some());
+        assert_eq!(
+            profiles.access_stats() - previous,
+            AccessStats {
+                hits: 1,
+                misses: 0,
+                peeks: 0,
+            }
+        );
+        assert_eq!(previous - profiles.access_stats(), AccessStats::default());
+    }
+
+    #[test]
+    fn counts_diff() {
+        let (a, b, c) = (profile(1), profile(2), profile(3));
+
+        let mut profiles = Profiles::default();
+        let empty = profiles.counts();
+        for profile in &[&a, &b, &c] {
+            profiles.put(profile.id(), Arc::clone(profile));
+        }
+        profiles.promote(&a.id());
+        let previous = profiles.counts();
+        assert_eq!(
+            previous,
+            BucketCounts {
+                dirty: 0,
+                pool: 2,
+                trusted: 1,
+            }
+        );
+
+        profiles.demote(&b.id());
+        profiles.remove(&c.id());
+        assert_eq!(
+            profiles.counts().diff(&previous),
+            CountsDelta {
+                dirty: 1,
+                pool: -2,
+                trusted: 0,
+            }
+        );
+        assert_eq!(
+            &profiles.counts() - &previous,
+            profiles.counts().diff(&previous)
+        );
+        assert_eq!(
+            &empty - &previous,
+            CountsDelta {
+                dirty: 0,
+                pool: -2,
+                trusted: -1,
+            }
+        );
+    }
+
+    #[test]
+    fn gc_forgets_dirty_and_stale_profiles() {
+        let now = Time::from(u32::from(Time::now()) + 120);
+        let updated_at = |seed: u8, time: u32| {
+            let mut rng = Seed::from([seed; Seed::SIZE]).into_rand_chacha();
+            let gossip = Gossip::new_at(
+                "127.0.0.1:9000".parse().unwrap(),
+                &ed25519::SecretKey::new(&mut rng),
+                Subscriptions::new().as_slice(),
+                Time::from(time),
+            );
+            Arc::new(Profile::from_gossip(gossip))
+        };
+        // exactly as old as the limit
+        let old = u32::from(now) - 300;
+        let (dirty, pool, trusted) = (updated_at(1, old), updated_at(2, old), profile(3));
+        let lifted = profile(4);
+
+        let mut profiles = Profiles::default();
+        profiles.test_insert_in_bucket(Arc::clone(&dirty), Bucket::Dirty);
+        profiles.test_insert_in_bucket(Arc::clone(&pool), Bucket::Pool);
+        profiles.test_insert_in_bucket(Arc::clone(&trusted), Bucket::Trusted);
+        profiles.put(lifted.id(), Arc::clone(&lifted));
+        profiles.demote(&lifted.id());
+
+        let report = profiles.gc(now, GcOptions::default());
+        assert_eq!(report, GcReport::default());
+
+        let report = profiles.gc(
+            now,
+            GcOptions {
+                forget_dirty_after: Some(Duration::from_secs(300)),
+                forget_stale_after: Some(Duration::from_secs(300)),
+                lift_quarantine_after: Some(Duration::from_secs(60)),
+            },
+        );
+
+        assert_eq!(report.forgotten_dirty, vec![dirty.id()]);
+        assert_eq!(report.forgotten_stale, vec![pool.id()]);
+        assert_eq!(report.quarantine_lifted, vec![lifted.id()]);
+        assert!(profiles.dirty().is_empty());
+        assert_eq!(profiles.bucket(&lifted.id()), Some(Bucket::Pool));
+        assert_eq!(profiles.pool().len(), 1);
+        assert!(profiles.trusted().contains(&trusted.id()));
+    }
+
+    #[test]
+    fn prefix_histogram_reflects_distribution() {
+        let mut uniform = Profiles::new(64, 64, 64);
+        for seed in 0..64 {
+            let profile = profile(seed);
+            uniform.put(profile.id(), profile);
+        }
+
+        let histogram = uniform.prefix_histogram(2);
+        assert_eq!(histogram.len(), 4);
+        assert_eq!(histogram.values().sum::<usize>(), 64);
+        assert!(histogram.values().all(|count| *count < 32));
+
+        let mut clustered = Profiles::new(64, 64, 64);
+        let mut seed = 0;
+        while clustered.pool().len() < 8 {
+            let profile = profile(seed);
+            if profile.id().as_ref()[0] >> 6 == 0b11 {
+                clustered.put(profile.id(), profile);
+            }
+            seed += 1;
+        }
+
+        let histogram = clustered.prefix_histogram(2);
+        assert_eq!(histogram.len(), 1);
+        assert_eq!(histogram.get(&0b11), Some(&8));
+
+        assert_eq!(clustered.prefix_histogram(0).get(&0), Some(&8));
+    }
+
+    #[quickcheck]
+    fn buckets_are_exclusive(buckets: Vec<Bucket>, operations: Vec<Operation>) -> bool {
+        let entries: Vec<(Arc<Profile>, Bucket)> = buckets
+            .into_iter()
+            .take(16)
+            .enumerate()
+            .map(|(seed, bucket)| (profile(seed as u8), bucket))
+            .collect();
+        if entries.is_empty() {
+            return true;
+        }
+
+        let mut profiles = Profiles::new(4, 4, 2);
+        for (profile, bucket) in entries.iter() {
+            profiles.test_insert_in_bucket(Arc::clone(profile), *bucket);
+        }
+
+        for operation in operations {
+            match operation {
+                Operation::Put(index) => {
+                    let (profile, _) = &entries[index % entries.len()];
+                    profiles.put(profile.id(), Arc::clone(profile));
+                }
+                Operation::Promote(index) => {
+                    let (profile, _) = &entries[index % entries.len()];
+                    profiles.promote(&profile.id());
+                }
+                Operation::Demote(index) => {
+                    let (profile, _) = &entries[index % entries.len()];
+                    profiles.demote(&profile.id());
+                }
+            }
+
+            let exclusive = entries
+                .iter()
+                .all(|(profile, _)| buckets_of(&profiles, &profile.id()) <= 1);
+            if !exclusive
+                || profiles.dirty.len() > profiles.dirty.cap()
+                || profiles.pool.len() > profiles.pool.cap()
+                || profiles.trusted.len() > profiles.trusted.cap()
+            {
+                return false;
+            }
+        }
+
+        true
     }
 }
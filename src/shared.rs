@@ -0,0 +1,128 @@
+use crate::{AccessStats, Bucket, Profile, Profiles};
+use keynesis::key::ed25519;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// the [`Profiles`] shared between threads, cloning it shares the same
+/// profiles
+///
+/// The operations that do not update the profiles (nor their recency)
+/// only take the read lock, the others (like [`Profiles::get`] which
+/// updates the recency of the profile) need the write lock and are
+/// available through [`SharedProfiles::with`].
+#[derive(Clone, Default)]
+pub struct SharedProfiles(Arc<RwLock<Profiles>>);
+
+impl SharedProfiles {
+    pub fn new(profiles: Profiles) -> Self {
+        Self(Arc::new(RwLock::new(profiles)))
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, Profiles> {
+        self.0.read().expect("the profiles lock is poisoned")
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Profiles> {
+        self.0.write().expect("the profiles lock is poisoned")
+    }
+
+    /// get the profile without updating its recency, see [`Profiles::peek`]
+    pub fn peek(&self, id: &ed25519::PublicKey) -> Option<Arc<Profile>> {
+        self.read().peek(id).cloned()
+    }
+
+    pub fn bucket(&self, id: &ed25519::PublicKey) -> Option<Bucket> {
+        self.read().bucket(id)
+    }
+
+    /// the available profiles (the promoted ones then the pooled ones),
+    /// see `impl IntoIterator for &Profiles`
+    pub fn available(&self) -> Vec<Arc<Profile>> {
+        self.read().into_iter().cloned().collect()
+    }
+
+    /// the number of profiles in the bucket
+    pub fn len(&self, bucket: Bucket) -> usize {
+        let profiles = self.read();
+        match bucket {
+            Bucket::Dirty => profiles.dirty().len(),
+            Bucket::Pool => profiles.pool().len(),
+            Bucket::Trusted => profiles.trusted().len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.read().is_empty()
+    }
+
+    pub fn access_stats(&self) -> AccessStats {
+        self.read().access_stats()
+    }
+
+    /// run `f` with the read lock held
+    pub fn read_with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&Profiles) -> R,
+    {
+        f(&self.read())
+    }
+
+    /// run `f` with the write lock held, for all the operations updating
+    /// the profiles or their recency
+    pub fn with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut Profiles) -> R,
+    {
+        f(&mut self.write())
+    }
+}
+
+impl From<Profiles> for SharedProfiles {
+    fn from(profiles: Profiles) -> Self {
+        Self::new(profiles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Gossip, Subscriptions};
+    use keynesis::Seed;
+    use std::thread;
+
+    fn profile(seed: u8) -> Arc<Profile> {
+        let mut rng = Seed::from([seed; Seed::SIZE]).into_rand_chacha();
+        let id = ed25519::SecretKey::new(&mut rng);
+        let address = "127.0.0.1:9000".parse().unwrap();
+        let gossip = Gossip::new(address, &id, Subscriptions::new().as_slice());
+        Arc::new(Profile::from_gossip(gossip))
+    }
+
+    #[test]
+    fn shared_between_threads() {
+        let shared = SharedProfiles::default();
+        assert!(shared.is_empty());
+
+        let handles: Vec<_> = (1..5)
+            .map(|seed| {
+                let shared = shared.clone();
+                thread::spawn(move || {
+                    let profile = profile(seed);
+                    shared.with(|profiles| profiles.put(profile.id(), profile))
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert!(handle.join().unwrap());
+        }
+
+        let id = profile(1).id();
+        assert_eq!(shared.len(Bucket::Pool), 4);
+        assert_eq!(shared.available().len(), 4);
+        assert_eq!(shared.peek(&id).map(|profile| profile.id()), Some(id));
+        assert_eq!(shared.access_stats().peeks, 1);
+
+        shared.with(|profiles| profiles.promote(&id));
+        assert_eq!(shared.bucket(&id), Some(Bucket::Trusted));
+        assert_eq!(shared.read_with(|profiles| profiles.trusted().len()), 1);
+    }
+}
@@ -0,0 +1,181 @@
+//! helpers to test custom [`Layer`]s
+//!
+//! This module is only available with the `testing` feature. It allows
+//! driving a layer through the same steps the [`Topology`] does (`reset`,
+//! `populate`, `view`) with deterministic profiles.
+//!
+//! ```
+//! use poldercast::{
+//!     layer::{Layer, Selection, ViewBuilder},
+//!     testing::TestTopology,
+//!     InterestLevel, PriorityMap, Profile, Topic,
+//! };
+//! use keynesis::key::ed25519;
+//!
+//! /// a layer selecting every peer it is populated with
+//! #[derive(Default)]
+//! struct Everyone(Vec<ed25519::PublicKey>);
+//!
+//! impl Layer for Everyone {
+//!     fn name(&self) -> &'static str {
+//!         "everyone"
+//!     }
+//!     fn view(&mut self, builder: &mut ViewBuilder) {
+//!         self.0.iter().for_each(|id| builder.add(id));
+//!     }
+//!     fn remove(&mut self, id: &ed25519::PublicKey) {
+//!         self.0.retain(|known| known != id);
+//!     }
+//!     fn reset(&mut self) {
+//!         self.0.clear();
+//!     }
+//!     fn subscribe(&mut self, _topic: Topic) {}
+//!     fn unsubscribe(&mut self, _topic: &Topic) {}
+//!     fn subscriptions(&self, _output: &mut PriorityMap<InterestLevel, Topic>) {}
+//!     fn populate(&mut self, _our_profile: &Profile, new_profile: &Profile) {
+//!         self.0.push(new_profile.id());
+//!     }
+//! }
+//!
+//! let topology = TestTopology::new((1..=3).map(|seed| TestTopology::peer(seed, &[])).collect());
+//!
+//! let mut layer = Everyone::default();
+//! topology.populate(&mut layer);
+//!
+//! let view = topology.view(&mut layer, Selection::Any);
+//! assert_eq!(view.len(), 3);
+//! ```
+//!
+//! [`Topology`]: crate::Topology
+
+use crate::{
+    layer::{Layer, Selection, ViewBuilder},
+    Gossip, InterestLevel, Profile, Subscription, Subscriptions, Topic,
+};
+use keynesis::{key::ed25519, Seed};
+use std::{collections::HashSet, net::SocketAddr};
+
+/// a set of profiles to drive a [`Layer`] with
+pub struct TestTopology {
+    profile: Profile,
+    peers: Vec<Profile>,
+}
+
+impl TestTopology {
+    /// the seed used to generate our own profile (see [`TestTopology::peer`])
+    pub const OUR_SEED: u8 = 0;
+
+    /// create a topology with the given peers, our profile is generated
+    /// with [`TestTopology::OUR_SEED`] and has no subscriptions
+    pub fn new(peers: Vec<Profile>) -> Self {
+        Self::new_with(Self::peer(Self::OUR_SEED, &[]), peers)
+    }
+
+    pub fn new_with(profile: Profile, peers: Vec<Profile>) -> Self {
+        Self { profile, peers }
+    }
+
+    /// the secret key deterministically generated from the given seed
+    pub fn secret_key(seed: u8) -> ed25519::SecretKey {
+        let mut rng = Seed::from([seed; Seed::SIZE]).into_rand_chacha();
+        ed25519::SecretKey::new(&mut rng)
+    }
+
+    /// create a profile deterministically from the given seed and with
+    /// the given subscriptions
+    pub fn peer(seed: u8, subscriptions: &[(Topic, InterestLevel)]) -> Profile {
+        let address = SocketAddr::from(([127, 0, 0, 1], 9000 + seed as u16));
+
+        let mut subs = Subscriptions::new();
+        for (topic, interest_level) in subscriptions {
+            subs.push(Subscription::new(*topic, *interest_level).as_slice())
+                .expect("not too many subscriptions");
+        }
+
+        Profile::from_gossip(Gossip::new(
+            address,
+            &Self::secret_key(seed),
+            subs.as_slice(),
+        ))
+    }
+
+    pub fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    pub fn peers(&self) -> &[Profile] {
+        &self.peers
+    }
+
+    /// reset the layer and populate it with all the peers, like the
+    /// [`Topology`](crate::Topology) does when adding peers
+    pub fn populate(&self, layer: &mut dyn Layer) {
+        layer.reset();
+        for peer in self.peers.iter() {
+            layer.populate(&self.profile, peer);
+        }
+    }
+
+    /// reset the layer and populate it with all the peers from the
+    /// perspective of the recipient, like the [`Topology`](crate::Topology)
+    /// does when selecting the gossips for the recipient
+    pub fn populate_for(&self, layer: &mut dyn Layer, recipient: &Profile) {
+        layer.reset();
+        for subscription in recipient.subscriptions().iter() {
+            layer.subscribe(subscription.topic());
+        }
+        for peer in self.peers.iter() {
+            layer.populate(recipient, peer);
+        }
+    }
+
+    /// build the view of the layer for the given selection, like the
+    /// [`Topology`](crate::Topology) does: the ids that are not peers are
+    /// ignored and so are the peers subscribed to the topic of
+    /// [`Selection::Exclude`]
+    pub fn view(&self, layer: &mut dyn Layer, selection: Selection) -> HashSet<ed25519::PublicKey> {
+        let mut builder = ViewBuilder::new(selection);
+        layer.view(&mut builder);
+        builder
+            .build_with(|id| self.peers.iter().find(|peer| peer.id() == *id))
+            .into_iter()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer::{Cyclon, Vicinity};
+
+    #[test]
+    fn drive_builtin_layers() {
+        let topology =
+            TestTopology::new((1..=5).map(|seed| TestTopology::peer(seed, &[])).collect());
+        let expected: HashSet<_> = topology.peers().iter().map(|peer| peer.id()).collect();
+
+        let mut cyclon = Cyclon::new(10);
+        topology.populate(&mut cyclon);
+        assert_eq!(topology.view(&mut cyclon, Selection::Any), expected);
+
+        let mut vicinity = Vicinity::new(2);
+        topology.populate_for(&mut vicinity, &topology.peers()[0]);
+        assert_eq!(topology.view(&mut vicinity, Selection::Any).len(), 2);
+    }
+
+    #[test]
+    fn view_exclude() {
+        let topic = Topic::new([1; Topic::SIZE]);
+        let level = InterestLevel::new(1);
+        let topology = TestTopology::new(vec![
+            TestTopology::peer(1, &[(topic, level)]),
+            TestTopology::peer(2, &[]),
+        ]);
+
+        let mut cyclon = Cyclon::new(10);
+        topology.populate(&mut cyclon);
+        let view = topology.view(&mut cyclon, Selection::Exclude { topic });
+        let expected: HashSet<_> = std::iter::once(topology.peers()[1].id()).collect();
+        assert_eq!(view, expected);
+    }
+}
@@ -241,7 +241,7 @@ impl Default for Subscriptions {
 
 /* Convert ***************************************************************** */
 
-impl<'a> TryFrom<&'a [u8]> for Topic {
+impl TryFrom<&[u8]> for Topic {
     type Error = std::array::TryFromSliceError;
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
         let bytes = value.try_into()?;
@@ -460,6 +460,16 @@ mod tests {
             .expect_err("Should have a max size reached error");
     }
 
+    #[test]
+    fn topic_try_from() {
+        let topic = Topic::try_from(&[1; Topic::SIZE][..]).unwrap();
+        assert_eq!(topic, Topic::new([1; Topic::SIZE]));
+
+        assert!(Topic::try_from(&[1; Topic::SIZE + 1][..]).is_err());
+        assert!(Topic::try_from(&[1; 3][..]).is_err());
+        assert!(Topic::try_from(&[][..]).is_err());
+    }
+
     #[test]
     fn topic_from_str() {
         let topic = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
@@ -1,38 +1,93 @@
 use crate::{
-    layer::{self, Layer, LayerBuilder, Selection, ViewBuilder},
-    Gossip, Profile, Profiles, Topic,
+    layer::{self, Layer, LayerBuilder, LayerDescription, LayerStateError, Selection, ViewBuilder},
+    Bucket, Gossip, GossipsBuilder, Profile, Profiles, Topic,
 };
 use keynesis::key::ed25519;
-use std::{net::SocketAddr, sync::Arc};
+use std::{collections::HashSet, net::SocketAddr, sync::Arc};
+use thiserror::Error;
 
 pub struct Topology {
     view_layers: Vec<Box<dyn Layer + Send + Sync>>,
     gossip_layers: Vec<Box<dyn Layer + Send + Sync>>,
     profile: Profile,
     profiles: Profiles,
+
+    demotion_breaker: Option<f64>,
+    demotion_breaker_tripped: bool,
+    demotion_breaker_hook: Option<DemotionBreakerHook>,
+    isolation_recovery: bool,
+}
+
+/// called with `true` when the demotion breaker trips and with `false`
+/// when it recovers, along with the ratio of demoted peers. See
+/// [`Topology::on_demotion_breaker`]
+pub type DemotionBreakerHook = Box<dyn FnMut(bool, f64) + Send + Sync>;
+
+/// the state of the [`Topology`]'s known peers, see [`Topology::export_state`]
+///
+/// The gossips of every bucket are ordered from the most recently used
+/// to the least recently used. The layers are identified by their name
+/// (see [`Layer::name`]) in the order they are queried, along with their
+/// internal state (see [`Layer::save_state`]) in the same order.
+#[derive(Debug, Clone, Default)]
+pub struct TopologyState {
+    pub trusted: Vec<Gossip>,
+    pub pool: Vec<Gossip>,
+    pub dirty: Vec<Gossip>,
+
+    pub view_layers: Vec<String>,
+    pub gossip_layers: Vec<String>,
+
+    pub view_layer_states: Vec<Vec<u8>>,
+    pub gossip_layer_states: Vec<Vec<u8>>,
+}
+
+/// the outcome of [`Topology::ingest_gossips`]
+///
+/// The gossips older than the profile we already know are in none of
+/// the lists.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IngestReport {
+    /// the peers we did not know about
+    pub learned: Vec<ed25519::PublicKey>,
+    /// the known peers whose profile has been updated
+    pub updated: Vec<ed25519::PublicKey>,
+    /// the gossips about ourselves or about demoted peers, and the gossips
+    /// about unknown peers that could not be added (their bucket is full
+    /// of pinned peers, see [`Topology::pin_peer`])
+    pub rejected: Vec<ed25519::PublicKey>,
+}
+
+#[derive(Debug, Error)]
+pub enum ImportStateError {
+    #[error("The layer {name} of the imported state is not available")]
+    MissingLayer { name: String },
+
+    #[error("The state of the layer {name} cannot be restored")]
+    LayerState {
+        name: String,
+        #[source]
+        error: LayerStateError,
+    },
 }
 
 struct DefaultBuilder;
 
 impl LayerBuilder for DefaultBuilder {
     fn build_for_view(&self) -> Vec<Box<dyn Layer + Send + Sync>> {
-        let mut layers: Vec<Box<dyn Layer + Send + Sync>> = Vec::with_capacity(3);
-
-        layers.push(Box::new(layer::Rings::new(4)));
-        layers.push(Box::new(layer::Vicinity::new(20)));
-        layers.push(Box::new(layer::Cyclon::new(20)));
-
-        layers
+        vec![
+            Box::new(layer::Rings::new(4)),
+            Box::new(layer::Vicinity::new(20)),
+            Box::new(layer::Cyclon::new(20)),
+        ]
     }
 
     fn build_for_gossip(&self) -> Vec<Box<dyn Layer + Send + Sync>> {
-        let mut layers: Vec<Box<dyn Layer + Send + Sync>> = Vec::with_capacity(3);
-
-        layers.push(Box::new(layer::Rings::new(10)));
-        layers.push(Box::new(layer::Vicinity::new(10)));
-        layers.push(Box::new(layer::Cyclon::new(10)));
-
-        layers
+        vec![
+            Box::new(layer::Rings::new(10)),
+            Box::new(layer::Vicinity::new(10)),
+            Box::new(layer::Cyclon::new(10)),
+        ]
     }
 }
 
@@ -47,12 +102,74 @@ impl Topology {
         LB: LayerBuilder,
     {
         let profile = Profile::new(address, id);
+        let mut profiles = Profiles::new(512, 256, 128);
+        profiles.track_forgotten();
+
         Self {
             view_layers: builder.build_for_view(),
             gossip_layers: builder.build_for_gossip(),
 
             profile,
-            profiles: Profiles::new(512, 256, 128),
+            profiles,
+
+            demotion_breaker: None,
+            demotion_breaker_tripped: false,
+            demotion_breaker_hook: None,
+            isolation_recovery: false,
+        }
+    }
+
+    /// when enabled, if all the peers end up demoted (see
+    /// [`Topology::remove_peer`]) the pinned ones (see
+    /// [`Topology::pin_peer`]) are moved back to the pool and to the
+    /// layers so we are not left isolated (see
+    /// [`Profiles::recover_from_isolation`]). Disabled by default.
+    pub fn enable_isolation_recovery(&mut self, enable: bool) {
+        self.isolation_recovery = enable;
+    }
+
+    /// stop demoting the peers of the pool (see [`Topology::remove_peer`])
+    /// while the ratio of demoted peers (see [`Profiles::quarantine_ratio`])
+    /// is at least the given `threshold`.
+    ///
+    /// When too many of the peers are demoted something is likely to be
+    /// wrong on our side (our connectivity may be broken) and we'd rather
+    /// not isolate ourselves further. `None` disables the breaker (the
+    /// default).
+    pub fn set_demotion_breaker(&mut self, threshold: Option<f64>) {
+        self.demotion_breaker = threshold;
+        self.update_demotion_breaker();
+    }
+
+    /// call `hook` every time the demotion breaker trips or recovers (see
+    /// [`Topology::set_demotion_breaker`])
+    pub fn on_demotion_breaker(&mut self, hook: DemotionBreakerHook) {
+        self.demotion_breaker_hook = Some(hook);
+    }
+
+    /// check whether the demotion breaker is tripped, in which case the
+    /// peers of the pool are not demoted anymore until the ratio of
+    /// demoted peers goes below the threshold.
+    pub fn is_demotion_breaker_tripped(&self) -> bool {
+        if let Some(threshold) = self.demotion_breaker {
+            self.profiles.quarantine_ratio() >= threshold
+        } else {
+            false
+        }
+    }
+
+    /// notify the hook of the demotion breaker if it tripped or recovered
+    /// since the last time it was checked
+    fn update_demotion_breaker(&mut self) {
+        let tripped = self.is_demotion_breaker_tripped();
+        if tripped == self.demotion_breaker_tripped {
+            return;
+        }
+
+        self.demotion_breaker_tripped = tripped;
+        let ratio = self.profiles.quarantine_ratio();
+        if let Some(hook) = self.demotion_breaker_hook.as_mut() {
+            hook(tripped, ratio);
         }
     }
 
@@ -91,12 +208,104 @@ impl Topology {
     /// The node will be removed from our layers, but it will not be
     /// entirely from our profile pool. We may share it to other nodes
     /// we may find it relevant
+    ///
+    /// If the demotion breaker is tripped (see [`Topology::set_demotion_breaker`])
+    /// the node is removed from our layers but a node of the pool is not
+    /// demoted, a trusted node is still moved back to the pool.
     pub fn remove_peer(&mut self, id: &ed25519::PublicKey) {
         for layer in self.view_layers.iter_mut() {
             layer.remove(id);
         }
 
-        self.profiles.demote(id);
+        if !self.is_demotion_breaker_tripped() || self.profiles.bucket(id) == Some(Bucket::Trusted)
+        {
+            self.profiles.demote(id);
+        }
+
+        if self.isolation_recovery {
+            for id in self.profiles.recover_from_isolation() {
+                if let Some(peer) = self.profiles.pool().peek(&id).cloned() {
+                    for layer in self.view_layers.iter_mut() {
+                        layer.populate(&self.profile, &peer);
+                    }
+                }
+            }
+        }
+
+        self.remove_forgotten_from_layers();
+        self.update_demotion_breaker();
+    }
+
+    /// remove from the layers the peers the profiles forgot about, for
+    /// example because they were evicted to make room for new peers
+    fn remove_forgotten_from_layers(&mut self) {
+        for id in self.profiles.take_forgotten() {
+            for layer in self.view_layers.iter_mut() {
+                layer.remove(&id);
+            }
+        }
+    }
+
+    /// forget everything about the peer (for example when it has been banned)
+    ///
+    /// Unlike [`Topology::remove_peer`] the peer is not demoted but removed
+    /// from the profiles, it will be added back if we receive a gossip
+    /// about it again.
+    pub fn forget_peer(&mut self, id: &ed25519::PublicKey) -> Option<Arc<Profile>> {
+        for layer in self.view_layers.iter_mut() {
+            layer.remove(id);
+        }
+
+        let removed = self.profiles.remove(id);
+        self.update_demotion_breaker();
+        removed
+    }
+
+    /// forget the peers for which the predicate returns `false`, see
+    /// [`Profiles::retain`]. Returns the ids of the forgotten peers.
+    pub fn retain_peers<F>(&mut self, predicate: F) -> Vec<ed25519::PublicKey>
+    where
+        F: FnMut(Bucket, &ed25519::PublicKey, &Arc<Profile>) -> bool,
+    {
+        let forgotten = self.profiles.retain(predicate);
+
+        for id in forgotten.iter() {
+            for layer in self.view_layers.iter_mut() {
+                layer.remove(id);
+            }
+        }
+
+        self.update_demotion_breaker();
+        forgotten
+    }
+
+    /// demote the peer on demand (for example after an out-of-band report of
+    /// misbehavior), even if it was promoted. See [`Profiles::quarantine`].
+    pub fn quarantine_peer(&mut self, id: &ed25519::PublicKey) -> bool {
+        for layer in self.view_layers.iter_mut() {
+            layer.remove(id);
+        }
+
+        let known = self.profiles.quarantine(id);
+        self.remove_forgotten_from_layers();
+        self.update_demotion_breaker();
+        known
+    }
+
+    /// move the demoted peer back to the pool and to the layers. See
+    /// [`Profiles::lift_quarantine`].
+    pub fn lift_quarantine_peer(&mut self, id: &ed25519::PublicKey) -> bool {
+        let known = self.profiles.lift_quarantine(id);
+        self.remove_forgotten_from_layers();
+        self.update_demotion_breaker();
+
+        if let Some(peer) = self.profiles.pool().peek(id).cloned() {
+            for layer in self.view_layers.iter_mut() {
+                layer.populate(&self.profile, &peer);
+            }
+        }
+
+        known
     }
 
     /// call this function to validate you were able to connect with the given
@@ -104,7 +313,20 @@ impl Topology {
     ///
     /// Call this function every time you successfully establish an handshake
     pub fn promote_peer(&mut self, id: &ed25519::PublicKey) {
-        self.profiles.promote(id)
+        self.profiles.promote(id);
+        self.remove_forgotten_from_layers();
+        self.update_demotion_breaker();
+    }
+
+    /// never evict the given peer to make room for other peers, typically
+    /// the bootstrap peers (see [`Profiles::pin`])
+    pub fn pin_peer(&mut self, id: ed25519::PublicKey) {
+        self.profiles.pin(id);
+    }
+
+    /// allow evicting the given peer again, see [`Topology::pin_peer`]
+    pub fn unpin_peer(&mut self, id: &ed25519::PublicKey) {
+        self.profiles.unpin(id);
     }
 
     /// add a Peer to the Topology
@@ -122,7 +344,10 @@ impl Topology {
 
         let peer = Arc::new(peer);
 
-        if !self.profiles.put(id, Arc::clone(&peer)) {
+        let added = self.profiles.put(id, Arc::clone(&peer));
+        self.remove_forgotten_from_layers();
+        self.update_demotion_breaker();
+        if !added {
             return false;
         }
 
@@ -133,24 +358,120 @@ impl Topology {
         true
     }
 
+    /// like [`Topology::add_peer`] but record the peer was gossiped by the
+    /// `source` peer. See [`Topology::forget_all_from_source`].
+    pub fn add_peer_from(&mut self, source: ed25519::PublicKey, peer: Profile) -> bool {
+        let id = peer.id();
+
+        let peer = Arc::new(peer);
+
+        let added = self.profiles.put_from(source, id, Arc::clone(&peer));
+        self.remove_forgotten_from_layers();
+        self.update_demotion_breaker();
+        if !added {
+            return false;
+        }
+
+        for layer in self.view_layers.iter_mut() {
+            layer.populate(&self.profile, &peer);
+        }
+
+        true
+    }
+
+    /// add all the gossips received from the `source` peer (see
+    /// [`Topology::add_peer_from`]) and report which peers are new to us
+    /// and which ones were only updated.
+    pub fn ingest_gossips(
+        &mut self,
+        source: ed25519::PublicKey,
+        gossips: Vec<Gossip>,
+    ) -> IngestReport {
+        let mut report = IngestReport::default();
+
+        for gossip in gossips {
+            let id = gossip.id();
+            if id == self.profile.id() {
+                report.rejected.push(id);
+                continue;
+            }
+
+            let known = self.profiles.bucket(&id).is_some();
+            if self.add_peer_from(source, Profile::from_gossip(gossip)) {
+                if known {
+                    report.updated.push(id);
+                } else {
+                    report.learned.push(id);
+                }
+            } else if !known || self.profiles.is_quarantined(&id) {
+                report.rejected.push(id);
+            }
+        }
+
+        report
+    }
+
+    /// call this function if the given peer was found to gossip fabricated
+    /// peers. All the peers only this `source` gossiped about are forgotten
+    /// and removed from our layers.
+    pub fn forget_all_from_source(
+        &mut self,
+        source: &ed25519::PublicKey,
+    ) -> Vec<ed25519::PublicKey> {
+        let forgotten = self.profiles.forget_all_from_source(source);
+
+        for id in forgotten.iter() {
+            for layer in self.view_layers.iter_mut() {
+                layer.remove(id);
+            }
+        }
+
+        self.update_demotion_breaker();
+        forgotten
+    }
+
     pub fn gossips_for(&mut self, recipient: &ed25519::PublicKey) -> Vec<Gossip> {
-        let mut gossips = Vec::with_capacity(1024);
+        self.gossips_for_with(recipient, GossipsBuilder::new())
+    }
 
+    /// select the gossips for the given recipient, using the limits of the
+    /// given builder.
+    ///
+    /// The gossips selected by the gossip layers are added in the order of
+    /// the ids (all the layers contribute to a single view). When the
+    /// builder's limits are reached the remaining gossips are dropped.
+    ///
+    /// Our own gossip is always sent last, whatever the topic. It is added
+    /// first to the builder, so it counts against the limits of the
+    /// builder and the gossips of the layers fill the remaining room.
+    pub fn gossips_for_with(
+        &mut self,
+        recipient: &ed25519::PublicKey,
+        mut gossips: GossipsBuilder,
+    ) -> Vec<Gossip> {
         let recipient = if let Some(recipient) = self.profiles.get(recipient) {
             Arc::clone(recipient)
         } else {
-            return gossips;
+            return gossips.build();
         };
 
+        gossips.add_last(self.profile.gossip().clone());
+
         let id = recipient.id();
 
         for layer in self.gossip_layers.iter_mut() {
             layer.reset();
         }
 
-        for subscription in recipient.subscriptions().iter() {
+        if let Some(topic) = gossips.topic() {
             for layer in self.gossip_layers.iter_mut() {
-                layer.subscribe(subscription.topic());
+                layer.subscribe(topic);
+            }
+        } else {
+            for subscription in recipient.subscriptions().iter() {
+                for layer in self.gossip_layers.iter_mut() {
+                    layer.subscribe(subscription.topic());
+                }
             }
         }
 
@@ -166,11 +487,11 @@ impl Topology {
         }
         let mut keys = builder.build();
 
-        keys.remove(&id); // remove the recipient's ID
+        keys.retain(|key| key != &id); // remove the recipient's ID
 
         for key in keys {
             if let Some(profile) = self.profiles.get(&key) {
-                gossips.push(profile.gossip().clone());
+                gossips.add(profile.gossip().clone());
             } else {
                 // we populated the gossip's view with the profiles' nodes
                 // so we should have all the entries that have been selected
@@ -179,9 +500,7 @@ impl Topology {
             }
         }
 
-        gossips.push(self.profile.gossip().clone());
-
-        gossips
+        gossips.build()
     }
 
     pub fn view(
@@ -194,11 +513,25 @@ impl Topology {
             builder.with_origin(*origin);
         }
 
+        self.view_with(builder)
+    }
+
+    /// like [`Topology::view`] but with a prepared builder, for example
+    /// to force some peers in the view (see [`ViewBuilder::must_include`]).
+    ///
+    /// The ids that are not known peers are ignored. With
+    /// [`Selection::Exclude`] the peers subscribed to the excluded topic
+    /// are removed from the view, unless they were forced in the view.
+    ///
+    /// The order of the returned profiles is deterministic: the peers
+    /// selected by the layers sorted by id, then the forced peers.
+    pub fn view_with(&mut self, mut builder: ViewBuilder) -> Vec<Arc<Profile>> {
         for layer in self.view_layers.iter_mut() {
             layer.view(&mut builder);
         }
 
-        let keys = builder.build();
+        let known = &self.profiles;
+        let keys = builder.build_with(|key| known.lookup(key).map(AsRef::as_ref));
 
         let mut profiles = Vec::with_capacity(keys.len());
 
@@ -211,10 +544,251 @@ impl Topology {
         profiles
     }
 
+    /// compute a small set of peers whose combined subscriptions cover all
+    /// the topics we are subscribed to.
+    ///
+    /// This is a greedy approximation of the set cover problem: at every
+    /// step we select the peer covering the most topics that are not covered
+    /// yet. The result is at most `ln(n) + 1` times larger than the optimal
+    /// set (`n` being the number of topics we are subscribed to).
+    ///
+    /// Only the promoted and pooled peers are considered (not the demoted ones)
+    /// and the topics none of these peers are subscribed to are ignored.
+    pub fn peers_for_broadcast(&self) -> Vec<ed25519::PublicKey> {
+        let mut uncovered: HashSet<Topic> = self
+            .profile
+            .subscriptions()
+            .iter()
+            .map(|sub| sub.topic())
+            .collect();
+
+        let mut candidates: Vec<(ed25519::PublicKey, HashSet<Topic>)> = self
+            .profiles
+            .trusted()
+            .iter()
+            .chain(self.profiles.pool().iter())
+            .map(|(id, profile)| {
+                let topics = profile
+                    .subscriptions()
+                    .iter()
+                    .map(|sub| sub.topic())
+                    .filter(|topic| uncovered.contains(topic))
+                    .collect();
+                (*id, topics)
+            })
+            .collect();
+
+        let mut peers = Vec::new();
+        while !uncovered.is_empty() {
+            let best = candidates
+                .iter()
+                .enumerate()
+                .map(|(index, (_, topics))| (index, topics.intersection(&uncovered).count()))
+                .max_by_key(|(_, count)| *count);
+
+            match best {
+                Some((index, count)) if count > 0 => {
+                    let (id, topics) = candidates.swap_remove(index);
+                    for topic in topics.iter() {
+                        uncovered.remove(topic);
+                    }
+                    peers.push(id);
+                }
+                _ => {
+                    // the remaining topics are not covered by any of the
+                    // known peers
+                    break;
+                }
+            }
+        }
+
+        peers
+    }
+
+    /// export the known peers so they can be imported in another
+    /// `Topology` (see [`Topology::import_state`])
+    pub fn export_state(&self) -> TopologyState {
+        fn gossips(cache: &lru::LruCache<ed25519::PublicKey, Arc<Profile>>) -> Vec<Gossip> {
+            cache
+                .iter()
+                .map(|(_, profile)| profile.gossip().clone())
+                .collect()
+        }
+
+        fn names(layers: &[Box<dyn Layer + Send + Sync>]) -> Vec<String> {
+            layers.iter().map(|layer| layer.name().to_owned()).collect()
+        }
+
+        fn states(layers: &[Box<dyn Layer + Send + Sync>]) -> Vec<Vec<u8>> {
+            layers.iter().map(|layer| layer.save_state()).collect()
+        }
+
+        TopologyState {
+            trusted: gossips(self.profiles.trusted()),
+            pool: gossips(self.profiles.pool()),
+            dirty: gossips(self.profiles.dirty()),
+
+            view_layers: names(&self.view_layers),
+            gossip_layers: names(&self.gossip_layers),
+
+            view_layer_states: states(&self.view_layers),
+            gossip_layer_states: states(&self.gossip_layers),
+        }
+    }
+
+    /// import the peers exported from another `Topology`, keeping them in
+    /// the same buckets and in the same order.
+    ///
+    /// The layers are reordered to be queried in the same order as in the
+    /// exported `Topology`, the layers that were not in the exported state
+    /// are queried last, and the state of the exported layers is restored.
+    /// If one of the exported layers is missing or fails to restore its
+    /// state nothing is imported (the layers keep their order and their
+    /// state) and an error is returned.
+    ///
+    /// The peers we already know are moved to the bucket they were
+    /// exported from and the ones that are not in the exported state are
+    /// forgotten, so we know the same peers as the exported `Topology`.
+    /// The layers are populated with the imported available
+    /// peers so the views are already built from the imported state.
+    pub fn import_state(&mut self, state: TopologyState) -> Result<(), ImportStateError> {
+        type Layers = [Box<dyn Layer + Send + Sync>];
+
+        // the indices of the layers in the order of the `names`, followed
+        // by the layers that are not named
+        fn order(layers: &Layers, names: &[String]) -> Result<Vec<usize>, ImportStateError> {
+            let mut order = Vec::with_capacity(layers.len());
+
+            for name in names {
+                let index = (0..layers.len())
+                    .find(|index| !order.contains(index) && layers[*index].name() == name)
+                    .ok_or_else(|| ImportStateError::MissingLayer { name: name.clone() })?;
+                order.push(index);
+            }
+
+            let unnamed: Vec<_> = (0..layers.len())
+                .filter(|index| !order.contains(index))
+                .collect();
+            order.extend(unnamed);
+            Ok(order)
+        }
+
+        // load the states in the layers at the given indices, returning
+        // the previous states of the layers so they can be restored with
+        // `rollback`. If a layer fails the previous states are restored.
+        fn load(
+            layers: &mut Layers,
+            order: &[usize],
+            states: &[Vec<u8>],
+        ) -> Result<Vec<(usize, Vec<u8>)>, ImportStateError> {
+            let mut previous = Vec::with_capacity(states.len());
+
+            for (&index, state) in order.iter().zip(states) {
+                let layer = &mut layers[index];
+                previous.push((index, layer.save_state()));
+
+                if let Err(error) = layer.load_state(state) {
+                    let name = layer.name().to_owned();
+                    rollback(layers, previous);
+                    return Err(ImportStateError::LayerState { name, error });
+                }
+            }
+
+            Ok(previous)
+        }
+
+        fn rollback(layers: &mut Layers, previous: Vec<(usize, Vec<u8>)>) {
+            for (index, state) in previous.into_iter().rev() {
+                // the layers can always restore the state they saved
+                let _ = layers[index].load_state(&state);
+            }
+        }
+
+        fn reorder(layers: &mut Vec<Box<dyn Layer + Send + Sync>>, order: &[usize]) {
+            let mut layers_by_index: Vec<_> = layers.drain(..).map(Some).collect();
+            *layers = order
+                .iter()
+                .filter_map(|index| layers_by_index[*index].take())
+                .collect();
+        }
+
+        let TopologyState {
+            trusted,
+            pool,
+            dirty,
+            view_layers,
+            gossip_layers,
+            view_layer_states,
+            gossip_layer_states,
+        } = state;
+
+        let view_order = order(&self.view_layers, &view_layers)?;
+        let gossip_order = order(&self.gossip_layers, &gossip_layers)?;
+        let previous = load(&mut self.view_layers, &view_order, &view_layer_states)?;
+        if let Err(error) = load(&mut self.gossip_layers, &gossip_order, &gossip_layer_states) {
+            rollback(&mut self.view_layers, previous);
+            return Err(error);
+        }
+        reorder(&mut self.view_layers, &view_order);
+        reorder(&mut self.gossip_layers, &gossip_order);
+
+        let exported: HashSet<_> = trusted
+            .iter()
+            .chain(pool.iter())
+            .chain(dirty.iter())
+            .map(Gossip::id)
+            .collect();
+        self.retain_peers(|_, id, _| exported.contains(id));
+
+        for (gossips, bucket) in [
+            (dirty, Bucket::Dirty),
+            (pool, Bucket::Pool),
+            (trusted, Bucket::Trusted),
+        ] {
+            // insert from the least recently used to the most recently used
+            // so the order of the LRU is preserved
+            for gossip in gossips.into_iter().rev() {
+                let peer = Arc::new(Profile::from_gossip(gossip));
+                self.profiles.move_to_bucket(Arc::clone(&peer), bucket);
+                self.remove_forgotten_from_layers();
+
+                if bucket == Bucket::Dirty {
+                    for layer in self.view_layers.iter_mut() {
+                        layer.remove(&peer.id());
+                    }
+                } else {
+                    for layer in self.view_layers.iter_mut() {
+                        layer.populate(&self.profile, &peer);
+                    }
+                }
+            }
+        }
+        self.remove_forgotten_from_layers();
+        self.update_demotion_breaker();
+
+        Ok(())
+    }
+
     pub fn get(&mut self, id: &ed25519::PublicKey) -> Option<&Arc<Profile>> {
         self.profiles.get(id)
     }
 
+    /// the configuration of the view layers, see [`Layer::describe`]
+    pub fn describe_view_layers(&self) -> Vec<LayerDescription> {
+        self.view_layers
+            .iter()
+            .map(|layer| layer.describe())
+            .collect()
+    }
+
+    /// the configuration of the gossip layers, see [`Layer::describe`]
+    pub fn describe_gossip_layers(&self) -> Vec<LayerDescription> {
+        self.gossip_layers
+            .iter()
+            .map(|layer| layer.describe())
+            .collect()
+    }
+
     pub fn peers(&self) -> &Profiles {
         &self.profiles
     }
@@ -223,3 +797,517 @@ impl Topology {
         &self.profile
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{InterestLevel, Subscription, Subscriptions};
+    use keynesis::{passport::block::Time, Seed};
+
+    fn secret_key(seed: u8) -> ed25519::SecretKey {
+        let mut rng = Seed::from([seed; Seed::SIZE]).into_rand_chacha();
+        ed25519::SecretKey::new(&mut rng)
+    }
+
+    fn peer(seed: u8, topics: &[Topic]) -> Profile {
+        let address: SocketAddr = format!("127.0.0.1:{}", 9000 + seed as u16).parse().unwrap();
+        let mut subscriptions = Subscriptions::new();
+        for topic in topics {
+            let sub = Subscription::new(*topic, InterestLevel::new(1));
+            subscriptions.push(sub.as_slice()).unwrap();
+        }
+        Profile::from_gossip(Gossip::new(
+            address,
+            &secret_key(seed),
+            subscriptions.as_slice(),
+        ))
+    }
+
+    fn ids(cache: &lru::LruCache<ed25519::PublicKey, Arc<Profile>>) -> Vec<ed25519::PublicKey> {
+        cache.iter().map(|(id, _)| *id).collect()
+    }
+
+    #[test]
+    fn export_import_state() {
+        let mut topology = Topology::new("127.0.0.1:9000".parse().unwrap(), &secret_key(0));
+        for seed in 1..10 {
+            assert!(topology.add_peer(peer(seed, &[])));
+        }
+        topology.promote_peer(&secret_key(1).public_key());
+        topology.promote_peer(&secret_key(2).public_key());
+        topology.remove_peer(&secret_key(3).public_key());
+
+        let mut imported = Topology::new("127.0.0.1:9000".parse().unwrap(), &secret_key(0));
+        for seed in 1..4 {
+            // already known, in other buckets than the exported ones
+            assert!(imported.add_peer(peer(seed, &[])));
+        }
+        imported.promote_peer(&secret_key(3).public_key());
+        // not exported, forgotten by the import
+        assert!(imported.add_peer(peer(20, &[])));
+        let state = topology.export_state();
+        imported.import_state(state.clone()).unwrap();
+
+        assert_eq!(
+            ids(imported.peers().trusted()),
+            ids(topology.peers().trusted())
+        );
+        assert_eq!(ids(imported.peers().pool()), ids(topology.peers().pool()));
+        assert_eq!(ids(imported.peers().dirty()), ids(topology.peers().dirty()));
+        assert_eq!(imported.peers().iter().count(), 9);
+        assert_eq!(imported.peers().bucket(&secret_key(20).public_key()), None);
+
+        // the order of the nodes of equal priority may change when the
+        // layers are populated, compare the saved nodes only
+        let entries = |states: &[Vec<u8>]| -> Vec<Vec<Vec<u8>>> {
+            states
+                .iter()
+                .map(|state| {
+                    let mut entries: Vec<_> = state.chunks(48).map(<[u8]>::to_vec).collect();
+                    entries.sort();
+                    entries
+                })
+                .collect()
+        };
+        let exported = imported.export_state();
+        assert_eq!(
+            entries(&exported.view_layer_states),
+            entries(&state.view_layer_states)
+        );
+        assert_eq!(exported.gossip_layer_states, state.gossip_layer_states);
+        assert!(state
+            .view_layer_states
+            .iter()
+            .any(|state| !state.is_empty()));
+
+        let view = |topology: &mut Topology| -> HashSet<ed25519::PublicKey> {
+            topology
+                .view(None, Selection::Any)
+                .iter()
+                .map(|profile| profile.id())
+                .collect()
+        };
+        assert_eq!(view(&mut imported), view(&mut topology));
+
+        let mut corrupted = state;
+        corrupted.view_layer_states[1].pop();
+        assert!(matches!(
+            Topology::new("127.0.0.1:9000".parse().unwrap(), &secret_key(0))
+                .import_state(corrupted),
+            Err(ImportStateError::LayerState { name, .. }) if name == "poldercast::vicinity"
+        ));
+    }
+
+    struct ReversedBuilder;
+
+    impl LayerBuilder for ReversedBuilder {
+        fn build_for_view(&self) -> Vec<Box<dyn Layer + Send + Sync>> {
+            let mut layers = DefaultBuilder.build_for_view();
+            layers.reverse();
+            layers
+        }
+
+        fn build_for_gossip(&self) -> Vec<Box<dyn Layer + Send + Sync>> {
+            let mut layers = DefaultBuilder.build_for_gossip();
+            layers.reverse();
+            layers
+        }
+    }
+
+    struct MissingBuilder;
+
+    impl LayerBuilder for MissingBuilder {
+        fn build_for_view(&self) -> Vec<Box<dyn Layer + Send + Sync>> {
+            vec![Box::new(layer::Cyclon::new(20))]
+        }
+
+        fn build_for_gossip(&self) -> Vec<Box<dyn Layer + Send + Sync>> {
+            DefaultBuilder.build_for_gossip()
+        }
+    }
+
+    fn names(layers: &[Box<dyn Layer + Send + Sync>]) -> Vec<&'static str> {
+        layers.iter().map(|layer| layer.name()).collect()
+    }
+
+    #[test]
+    fn import_state_preserves_layers_order() {
+        let address = "127.0.0.1:9000".parse().unwrap();
+        let topology = Topology::new(address, &secret_key(0));
+        let state = topology.export_state();
+
+        let mut imported = Topology::new_with(address, &secret_key(0), ReversedBuilder);
+        assert_ne!(names(&imported.view_layers), names(&topology.view_layers));
+
+        imported.import_state(state.clone()).unwrap();
+        assert_eq!(names(&imported.view_layers), names(&topology.view_layers));
+        assert_eq!(
+            names(&imported.gossip_layers),
+            names(&topology.gossip_layers)
+        );
+
+        let mut missing = Topology::new_with(address, &secret_key(0), MissingBuilder);
+        let gossip_layers = names(&missing.gossip_layers);
+        assert!(matches!(
+            missing.import_state(state),
+            Err(ImportStateError::MissingLayer { name }) if name == "poldercast::rings"
+        ));
+        assert_eq!(names(&missing.view_layers), vec!["poldercast::cyclon"]);
+        assert_eq!(names(&missing.gossip_layers), gossip_layers);
+    }
+
+    #[test]
+    fn import_state_is_atomic() {
+        let address = "127.0.0.1:9000".parse().unwrap();
+        let mut topology = Topology::new(address, &secret_key(0));
+        for seed in 1..10 {
+            assert!(topology.add_peer(peer(seed, &[])));
+        }
+        let mut state = topology.export_state();
+        state.gossip_layer_states[1].push(0);
+
+        let mut imported = Topology::new_with(address, &secret_key(0), ReversedBuilder);
+        for seed in 10..15 {
+            assert!(imported.add_peer(peer(seed, &[])));
+        }
+        let before = imported.export_state();
+        assert!(before
+            .view_layer_states
+            .iter()
+            .any(|state| !state.is_empty()));
+
+        assert!(matches!(
+            imported.import_state(state),
+            Err(ImportStateError::LayerState { name, .. }) if name == "poldercast::vicinity"
+        ));
+        let after = imported.export_state();
+        assert_eq!(after.view_layers, before.view_layers);
+        assert_eq!(after.view_layer_states, before.view_layer_states);
+        assert_eq!(after.gossip_layers, before.gossip_layers);
+        assert_eq!(after.gossip_layer_states, before.gossip_layer_states);
+        assert_eq!(imported.peers().iter().count(), 5);
+    }
+
+    #[test]
+    fn view_must_include() {
+        let mut topology = Topology::new("127.0.0.1:9000".parse().unwrap(), &secret_key(0));
+        for seed in 1..4 {
+            assert!(topology.add_peer(peer(seed, &[])));
+        }
+        let relay = secret_key(1).public_key();
+        topology.remove_peer(&relay);
+
+        let view = |profiles: Vec<Arc<Profile>>| -> HashSet<ed25519::PublicKey> {
+            profiles.iter().map(|profile| profile.id()).collect()
+        };
+        assert!(!view(topology.view(None, Selection::Any)).contains(&relay));
+
+        let mut builder = ViewBuilder::new(Selection::Any);
+        builder
+            .must_include(relay)
+            .must_include(secret_key(9).public_key());
+        let forced = view(topology.view_with(builder));
+        let expected: HashSet<_> = (1..4).map(|seed| secret_key(seed).public_key()).collect();
+        assert_eq!(forced, expected);
+
+        // the unknown forced peer does not take the place of a known one
+        let mut builder = ViewBuilder::new(Selection::Any);
+        builder
+            .with_max_size(2)
+            .must_include(secret_key(9).public_key())
+            .must_include(relay);
+        let forced = topology.view_with(builder);
+        assert_eq!(forced.len(), 2);
+        assert_eq!(forced[1].id(), relay);
+    }
+
+    #[test]
+    fn view_exclude() {
+        let topic = Topic::new([1; Topic::SIZE]);
+        let other = Topic::new([2; Topic::SIZE]);
+
+        let mut topology = Topology::new("127.0.0.1:9000".parse().unwrap(), &secret_key(0));
+        assert!(topology.add_peer(peer(1, &[topic])));
+        assert!(topology.add_peer(peer(2, &[topic, other])));
+        assert!(topology.add_peer(peer(3, &[other])));
+        assert!(topology.add_peer(peer(4, &[])));
+
+        let view = |profiles: Vec<Arc<Profile>>| -> HashSet<ed25519::PublicKey> {
+            profiles.iter().map(|profile| profile.id()).collect()
+        };
+
+        let expected: HashSet<_> = vec![3, 4]
+            .into_iter()
+            .map(|seed| secret_key(seed).public_key())
+            .collect();
+        assert_eq!(
+            view(topology.view(None, Selection::Exclude { topic })),
+            expected
+        );
+
+        // the excluded peers do not take the place of the other ones
+        let mut builder = ViewBuilder::new(Selection::Exclude { topic });
+        builder.with_max_size(2);
+        assert_eq!(view(topology.view_with(builder)), expected);
+    }
+
+    #[test]
+    fn evicted_peers_are_removed_from_layers() {
+        let mut topology = Topology::new("127.0.0.1:9000".parse().unwrap(), &secret_key(0));
+        topology.profiles.resize(Bucket::Pool, 2);
+
+        for seed in 1..=3 {
+            assert!(topology.add_peer(peer(seed, &[])));
+        }
+        let evicted = secret_key(1).public_key();
+        assert_eq!(topology.peers().bucket(&evicted), None);
+
+        let mut builder = ViewBuilder::new(Selection::Any);
+        for layer in topology.view_layers.iter_mut() {
+            layer.view(&mut builder);
+        }
+        let view = builder.build();
+        assert_eq!(view.len(), 2);
+        assert!(!view.contains(&evicted));
+    }
+
+    #[test]
+    fn describe_layers() {
+        let topology = Topology::new("127.0.0.1:9000".parse().unwrap(), &secret_key(0));
+        let describe = |descriptions: Vec<LayerDescription>| -> Vec<String> {
+            descriptions.iter().map(ToString::to_string).collect()
+        };
+
+        assert_eq!(
+            describe(topology.describe_view_layers()),
+            vec![
+                "poldercast::rings(length=4)",
+                "poldercast::vicinity(length=20)",
+                "poldercast::cyclon(length=20)",
+            ]
+        );
+        assert_eq!(
+            describe(topology.describe_gossip_layers()),
+            vec![
+                "poldercast::rings(length=10)",
+                "poldercast::vicinity(length=10)",
+                "poldercast::cyclon(length=10)",
+            ]
+        );
+    }
+
+    #[test]
+    fn gossips_within_max_bytes() {
+        let mut topology = Topology::new("127.0.0.1:9000".parse().unwrap(), &secret_key(0));
+        for seed in 1..10 {
+            assert!(topology.add_peer(peer(seed, &[])));
+        }
+        let recipient = secret_key(1).public_key();
+
+        let all = topology.gossips_for(&recipient);
+        assert_eq!(all.len(), 9);
+        assert_eq!(all.last().unwrap().id(), topology.self_profile().id());
+
+        let max_bytes = 3 * all[0].wire_size() + 1;
+        let gossips =
+            topology.gossips_for_with(&recipient, GossipsBuilder::new().with_max_bytes(max_bytes));
+
+        assert_eq!(gossips.len(), 3);
+        assert_eq!(gossips[2].id(), topology.self_profile().id());
+        let size: usize = gossips.iter().map(|gossip| gossip.as_ref().len()).sum();
+        assert!(size <= max_bytes);
+
+        let max_bytes = all[0].wire_size();
+        let gossips =
+            topology.gossips_for_with(&recipient, GossipsBuilder::new().with_max_bytes(max_bytes));
+        assert_eq!(gossips.len(), 1);
+        assert_eq!(gossips[0].id(), topology.self_profile().id());
+
+        let gossips = topology.gossips_for_with(&recipient, GossipsBuilder::new().with_max(2));
+        assert_eq!(gossips.len(), 2);
+        assert_eq!(gossips[1].id(), topology.self_profile().id());
+        let gossips = topology.gossips_for_with(&recipient, GossipsBuilder::new().with_max(0));
+        assert!(gossips.is_empty());
+    }
+
+    #[test]
+    fn gossips_for_topic() {
+        let topic = Topic::new([1; Topic::SIZE]);
+        let other = Topic::new([2; Topic::SIZE]);
+
+        let mut topology = Topology::new("127.0.0.1:9000".parse().unwrap(), &secret_key(0));
+        assert!(topology.add_peer(peer(1, &[topic, other])));
+        for seed in 2..5 {
+            assert!(topology.add_peer(peer(seed, &[topic])));
+        }
+        for seed in 5..8 {
+            assert!(topology.add_peer(peer(seed, &[other])));
+        }
+        let recipient = secret_key(1).public_key();
+
+        let all = topology.gossips_for(&recipient);
+        assert!(all
+            .iter()
+            .any(|gossip| gossip.id() == secret_key(5).public_key()));
+
+        let mut gossips = topology.gossips_for_with(&recipient, GossipsBuilder::for_topic(topic));
+        assert_eq!(gossips.pop().unwrap().id(), topology.self_profile().id());
+        let ids: HashSet<_> = gossips.iter().map(|gossip| gossip.id()).collect();
+        let expected: HashSet<_> = (2..5).map(|seed| secret_key(seed).public_key()).collect();
+        assert_eq!(ids, expected);
+    }
+
+    #[test]
+    fn ingest_gossips() {
+        let mut topology = Topology::new("127.0.0.1:9000".parse().unwrap(), &secret_key(0));
+        let source = secret_key(9).public_key();
+
+        let long_ago = Time::from(u32::from(Time::now()) - 60);
+        let old_peer = |seed: u8| {
+            let address = format!("127.0.0.1:{}", 9000 + seed as u16).parse().unwrap();
+            let subscriptions = Subscriptions::new();
+            Profile::from_gossip(Gossip::new_at(
+                address,
+                &secret_key(seed),
+                subscriptions.as_slice(),
+                long_ago,
+            ))
+        };
+
+        let known = peer(1, &[]);
+        assert!(topology.add_peer(Profile::from_gossip(known.gossip().clone())));
+        assert!(topology.add_peer(old_peer(2)));
+        assert!(topology.add_peer(old_peer(3)));
+        topology.remove_peer(&secret_key(3).public_key());
+
+        let gossips = vec![
+            topology.self_profile().gossip().clone(),
+            known.gossip().clone(),
+            peer(2, &[]).gossip().clone(),
+            peer(3, &[]).gossip().clone(),
+            peer(4, &[]).gossip().clone(),
+        ];
+        let report = topology.ingest_gossips(source, gossips);
+
+        assert_eq!(
+            report,
+            IngestReport {
+                learned: vec![secret_key(4).public_key()],
+                updated: vec![secret_key(2).public_key()],
+                rejected: vec![topology.self_profile().id(), secret_key(3).public_key()],
+            }
+        );
+
+        topology.profiles.resize(Bucket::Pool, 3);
+        for seed in [1, 2, 4].iter() {
+            topology.pin_peer(secret_key(*seed).public_key());
+        }
+        let report = topology.ingest_gossips(source, vec![peer(5, &[]).gossip().clone()]);
+        assert_eq!(
+            report,
+            IngestReport {
+                rejected: vec![secret_key(5).public_key()],
+                ..IngestReport::default()
+            }
+        );
+    }
+
+    #[test]
+    fn demotion_breaker_trips() {
+        let mut topology = Topology::new("127.0.0.1:9000".parse().unwrap(), &secret_key(0));
+        topology.set_demotion_breaker(Some(0.5));
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&events);
+        topology.on_demotion_breaker(Box::new(move |tripped, _| {
+            recorded.lock().unwrap().push(tripped);
+        }));
+        for seed in 1..=4 {
+            assert!(topology.add_peer(peer(seed, &[])));
+        }
+        topology.promote_peer(&secret_key(4).public_key());
+
+        topology.remove_peer(&secret_key(1).public_key());
+        assert!(!topology.is_demotion_breaker_tripped());
+        topology.remove_peer(&secret_key(2).public_key());
+        assert!(topology.is_demotion_breaker_tripped());
+        assert_eq!(*events.lock().unwrap(), vec![true]);
+
+        // the trusted peers are still moved back to the pool
+        topology.remove_peer(&secret_key(4).public_key());
+        assert_eq!(
+            topology.peers().bucket(&secret_key(4).public_key()),
+            Some(Bucket::Pool)
+        );
+
+        topology.remove_peer(&secret_key(3).public_key());
+        assert_eq!(topology.peers().dirty().len(), 2);
+        assert!(topology
+            .peers()
+            .pool()
+            .contains(&secret_key(3).public_key()));
+
+        topology.set_demotion_breaker(None);
+        assert_eq!(*events.lock().unwrap(), vec![true, false]);
+        topology.remove_peer(&secret_key(3).public_key());
+        assert_eq!(topology.peers().dirty().len(), 3);
+    }
+
+    #[test]
+    fn isolation_recovery() {
+        let mut topology = Topology::new("127.0.0.1:9000".parse().unwrap(), &secret_key(0));
+        topology.enable_isolation_recovery(true);
+        for seed in 1..=3 {
+            assert!(topology.add_peer(peer(seed, &[])));
+        }
+        topology.pin_peer(secret_key(1).public_key());
+        topology.pin_peer(secret_key(3).public_key());
+
+        topology.remove_peer(&secret_key(1).public_key());
+        topology.remove_peer(&secret_key(2).public_key());
+        assert_eq!(topology.peers().dirty().len(), 2);
+
+        topology.remove_peer(&secret_key(3).public_key());
+        assert_eq!(topology.peers().dirty().len(), 1);
+        assert_eq!(topology.peers().pool().len(), 2);
+        assert_eq!(
+            topology.peers().bucket(&secret_key(2).public_key()),
+            Some(Bucket::Dirty)
+        );
+        assert_eq!(topology.view(None, Selection::Any).len(), 2);
+    }
+
+    #[test]
+    fn peers_for_broadcast_covers_all_topics() {
+        let topics: Vec<Topic> = (1..=5).map(|i| Topic::new([i; Topic::SIZE])).collect();
+
+        let mut topology = Topology::new("127.0.0.1:9000".parse().unwrap(), &secret_key(0));
+        for topic in topics.iter() {
+            topology
+                .profile
+                .subscriptions_mut()
+                .put(InterestLevel::new(1), *topic);
+        }
+
+        let large = peer(1, &topics[0..3]);
+        let large_id = large.id();
+        assert!(topology.add_peer(large));
+        assert!(topology.add_peer(peer(2, &topics[0..1])));
+        assert!(topology.add_peer(peer(3, &topics[1..2])));
+        assert!(topology.add_peer(peer(4, &topics[2..4])));
+        assert!(topology.add_peer(peer(5, &topics[3..5])));
+
+        let peers = topology.peers_for_broadcast();
+
+        let mut covered = HashSet::new();
+        for id in peers.iter() {
+            let profile = topology.get(id).unwrap();
+            for sub in profile.subscriptions().iter() {
+                covered.insert(sub.topic());
+            }
+        }
+
+        assert!(topics.iter().all(|topic| covered.contains(topic)));
+        assert_eq!(peers.len(), 2);
+        assert!(peers.contains(&large_id));
+    }
+}